@@ -0,0 +1,167 @@
+//! Where linked files and images are copied to in the output directory.
+
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use siphasher::sip128::{Hasher128, SipHasher13};
+
+use crate::diagnostics;
+
+/// Files and folders the build writes itself, which assets must not replace.
+const RESERVED: &[&str] = &["index.html", "cheatsheet.pdf", ".cache.json", "css"];
+
+/// Folder assets from outside the vault are mirrored into, with `..` turned
+/// into this.
+const PARENT: &str = "_parent";
+
+/// Folder of the assets named after their content.
+const HASHED: &str = "assets";
+
+#[derive(
+    clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// Keep the paths relative to the vault root, `img/a.png`.
+    #[default]
+    Mirror,
+    /// Name files after their content, `assets/a-0123456789abcdef.png`, so
+    /// browsers can cache them for good.
+    Hash,
+}
+
+/// Path of the copy of `path` in the output directory, relative to it.
+pub fn output_path(path: &Path, root: &Path, layout: Layout) -> PathBuf {
+    match layout {
+        Layout::Mirror => mirror(path, root),
+        Layout::Hash => {
+            let mut hasher = SipHasher13::new();
+            match std::fs::read(path) {
+                Ok(contents) => hasher.write(&contents),
+                // Reported when the file is copied
+                Err(_) => hasher.write(path.as_os_str().as_encoded_bytes()),
+            }
+            let hash = hasher.finish128().as_u128() as u64;
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let name = match path.extension() {
+                Some(extension) => {
+                    format!("{}-{:016x}.{}", stem, hash, extension.to_string_lossy())
+                }
+                None => format!("{}-{:016x}", stem, hash),
+            };
+            Path::new(HASHED).join(name)
+        }
+    }
+}
+
+fn mirror(path: &Path, root: &Path) -> PathBuf {
+    let absolute = |path: &Path| {
+        let path = if path.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            path
+        };
+        normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+    };
+    let relative = pathdiff::diff_paths(absolute(path), absolute(root))
+        .unwrap_or_else(|| PathBuf::from(path.file_name().unwrap_or_default()));
+    relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name),
+            Component::ParentDir => Some(PARENT.as_ref()),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => None,
+        })
+        .collect()
+}
+
+/// Removes `.` and the folders `..` leads out of, without looking at the
+/// file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// URL of an asset at the `relative` path in the output directory.
+pub fn url(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Media type of a file, by its extension.
+pub fn content_type(file: &Path) -> &'static str {
+    let extension = file
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("pdf") => "application/pdf",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Pairs the assets with the paths they are copied to, reporting those that
+/// would replace another asset or a file of the build, which are left out.
+pub fn plan(assets: &[PathBuf], root: &Path, layout: Layout) -> Vec<(PathBuf, PathBuf)> {
+    // Lowercase, as the output may end up on a case insensitive file system
+    let mut taken: HashMap<String, (&Path, PathBuf)> = HashMap::new();
+    let mut copies = vec![];
+    for path in assets {
+        let relative = output_path(path, root, layout);
+        let key = url(&relative).to_lowercase();
+        if RESERVED.contains(&key.split('/').next().unwrap_or_default()) {
+            diagnostics::error(format_args!(
+                "`{}` would be copied to `{}`, which the build writes itself",
+                path.display(),
+                relative.display()
+            ));
+            continue;
+        }
+        match taken.get(&key) {
+            // The same file, or one with the same contents for hashed names
+            Some((_, other)) if *other == relative => continue,
+            Some((other, _)) => {
+                diagnostics::error(format_args!(
+                    "`{}` and `{}` would both be copied to `{}`",
+                    other.display(),
+                    path.display(),
+                    relative.display()
+                ));
+                continue;
+            }
+            None => {
+                taken.insert(key, (path, relative.clone()));
+            }
+        }
+        copies.push((path.clone(), relative));
+    }
+    copies
+}
@@ -0,0 +1,145 @@
+//! Cache of rendered blocks, kept in the output directory between runs so
+//! only blocks that changed are rendered again.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use siphasher::sip128::{Hasher128, SipHasher13};
+
+use crate::assets::Layout;
+use crate::diagnostics;
+use crate::error::Error;
+use crate::vault::{self, Vault};
+
+const FILE: &str = ".cache.json";
+
+/// Version of the HTML blocks are rendered to. Bump it whenever the rendered
+/// HTML changes, so blocks rendered before are rendered again.
+const FORMAT: u32 = 1;
+
+/// Size and modification time of a file.
+pub type Stamp = (u64, Option<SystemTime>);
+
+/// A block rendered to HTML, and what else rendering it did.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Rendered {
+    pub html: String,
+    /// Files the block refers to, which are copied into the output directory.
+    pub assets: Vec<PathBuf>,
+    /// URLs of remote images and files the block refers to.
+    #[serde(default)]
+    pub remote: Vec<String>,
+    /// Sizes and modification times of the `assets`, if their contents end
+    /// up in the HTML, as they do with hashed names.
+    #[serde(default)]
+    pub stamps: Vec<Option<Stamp>>,
+    /// Files the block refers to that did not exist.
+    #[serde(default)]
+    pub missing: Vec<PathBuf>,
+    /// Warnings and errors printed while rendering.
+    pub diagnostics: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Cache {
+    /// Versions of the program and of the HTML the blocks were rendered
+    /// with.
+    version: String,
+    blocks: HashMap<String, Rendered>,
+    /// Keys of the blocks used since the last save, the others are dropped.
+    #[serde(skip)]
+    used: HashSet<String>,
+}
+
+impl Cache {
+    /// Loads the cache from the output directory `dir`, or starts an empty one
+    /// if there is none or it was written by another version.
+    pub fn load(dir: &Path) -> Self {
+        let version = format!("{}+{}", env!("CARGO_PKG_VERSION"), FORMAT);
+        std::fs::read_to_string(dir.join(FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str::<Cache>(&json).ok())
+            .filter(|cache| cache.version == version)
+            .unwrap_or_else(|| Cache {
+                version,
+                ..Default::default()
+            })
+    }
+
+    /// Writes the blocks used since the last save to the output directory.
+    pub fn save(&mut self, dir: &Path) -> Result<(), Error> {
+        let used = std::mem::take(&mut self.used);
+        self.blocks.retain(|key, _| used.contains(key));
+        let path = dir.join(FILE);
+        std::fs::write(&path, serde_json::to_string(self).unwrap())
+            .map_err(|source| Error::Write { path, source })
+    }
+
+    /// Returns the block rendered for `key`, calling `render` if it is not in
+    /// the cache or the files it refers to changed: one of its assets was
+    /// removed or its stamp changed, or a missing file was added. Messages
+    /// printed while rendering are printed again whenever the block is
+    /// reused.
+    pub fn render(&mut self, key: u128, render: impl FnOnce() -> Rendered) -> Rendered {
+        let key = format!("{:032x}", key);
+        self.used.insert(key.clone());
+        if let Some(rendered) = self.blocks.get(&key) {
+            if rendered.assets.iter().all(|path| path.is_file())
+                && !rendered.missing.iter().any(|path| path.exists())
+                && (rendered.stamps.is_empty() || rendered.stamps == stamps(&rendered.assets))
+            {
+                diagnostics::replay(&rendered.diagnostics);
+                return rendered.clone();
+            }
+        }
+        let ((mut rendered, missing), messages) =
+            diagnostics::capture(|| vault::capture_missing(render));
+        rendered.missing = missing;
+        rendered.diagnostics = messages;
+        self.blocks.insert(key, rendered.clone());
+        rendered
+    }
+}
+
+/// Hashes everything a rendered block depends on into a key.
+pub fn key(value: impl Hash) -> u128 {
+    let mut hasher = SipHasher13::new();
+    value.hash(&mut hasher);
+    hasher.finish128().as_u128()
+}
+
+/// Stamps of the `assets`, `None` for those that can not be read.
+pub fn stamps(assets: &[PathBuf]) -> Vec<Option<Stamp>> {
+    assets
+        .iter()
+        .map(|path| {
+            let metadata = std::fs::metadata(path).ok()?;
+            Some((metadata.len(), metadata.modified().ok()))
+        })
+        .collect()
+}
+
+/// Key of everything links are resolved against: the notes with their
+/// blocks. Files the blocks refer to are checked by each block, see
+/// [`Cache::render`].
+///
+/// A block only changes with the rest of the vault if a heading or title is
+/// renamed, or a note is added or removed, so this is hashed once per run.
+pub fn vault_key(vault: &Vault, layout: Layout) -> u128 {
+    let mut hasher = SipHasher13::new();
+    layout.hash(&mut hasher);
+    vault.root.hash(&mut hasher);
+    vault.attachments.hash(&mut hasher);
+    vault.options.bits().hash(&mut hasher);
+    for note in &vault.notes {
+        note.path.hash(&mut hasher);
+        note.name.hash(&mut hasher);
+        note.meta.title.hash(&mut hasher);
+        note.options.bits().hash(&mut hasher);
+        note.blocks.hash(&mut hasher);
+    }
+    hasher.finish128().as_u128()
+}
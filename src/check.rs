@@ -0,0 +1,162 @@
+//! Checks only `check` runs, for things that do not break a build but are
+//! likely mistakes.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+
+use crate::code;
+use crate::diagnostics::{self, Span};
+use crate::vault::{BlockIndex, Note};
+
+#[derive(Default)]
+pub struct Checker<'a> {
+    /// Title of the first block with each title, by the lowercase title.
+    titles: HashMap<String, Span<'a>>,
+}
+
+impl<'a> Checker<'a> {
+    /// Reports footnote references without a definition in the note, which
+    /// are rendered as text.
+    pub fn note(&mut self, note: &'a Note) {
+        // Text of undefined references is split at the brackets, code is
+        // left as it is
+        let mut texts: Vec<Range<usize>> = vec![];
+        let mut in_code_block = false;
+        for (event, range) in note.events() {
+            match event {
+                Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                Event::End(TagEnd::CodeBlock) => in_code_block = false,
+                Event::Text(_) if !in_code_block => match texts.last_mut() {
+                    Some(text) if text.end == range.start => text.end = range.end,
+                    _ => texts.push(range),
+                },
+                _ => {}
+            }
+        }
+        for text in texts {
+            undefined_footnotes(note, text);
+        }
+    }
+
+    /// Reports blocks without a heading or without content, and titles used
+    /// by an earlier block, which links can not tell apart.
+    pub fn block(&mut self, note: &'a Note, block: &BlockIndex, events: &[(Event, Range<usize>)]) {
+        code_blocks(note, events);
+        let mut in_metadata_block = false;
+        let mut events = events.iter().filter(|(event, _)| match event {
+            Event::Start(Tag::MetadataBlock(_)) => {
+                in_metadata_block = true;
+                false
+            }
+            Event::End(TagEnd::MetadataBlock(_)) => {
+                in_metadata_block = false;
+                false
+            }
+            _ => !in_metadata_block,
+        });
+        let Some((first, range)) = events.next() else {
+            return;
+        };
+        let span = Span::new(&note.path, &note.src, range.clone());
+
+        let Some(title) = &block.title else {
+            diagnostics::warning_at(&span, "block has no heading");
+            return;
+        };
+        if matches!(first, Event::Start(Tag::Heading { .. })) {
+            let mut content =
+                events.skip_while(|(event, _)| !matches!(event, Event::End(TagEnd::Heading(_))));
+            if content.nth(1).is_none() {
+                diagnostics::warning_at(&span, format_args!("block `{}` is empty", title));
+            }
+        }
+        match self.titles.get(&title.to_lowercase()) {
+            Some(first) => {
+                let (line, column) = first.line_column();
+                diagnostics::warning_at(
+                    &span,
+                    format_args!(
+                        "block title `{}` is used before at {}:{}:{}",
+                        title,
+                        first.path.display(),
+                        line,
+                        column
+                    ),
+                );
+            }
+            None => {
+                self.titles.insert(title.to_lowercase(), span);
+            }
+        }
+    }
+}
+
+/// Reports options of fenced code blocks that are not known, and lines to
+/// highlight the code does not have.
+fn code_blocks(note: &Note, events: &[(Event, Range<usize>)]) {
+    let mut block = None;
+    for (event, range) in events {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                block = Some((code::Info::parse(info), range, 0));
+            }
+            Event::Text(text) => {
+                if let Some((_, _, lines)) = &mut block {
+                    *lines += text.lines().count();
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                let Some((info, range, lines)) = block.take() else {
+                    continue;
+                };
+                let span = Span::new(&note.path, &note.src, range.clone());
+                for word in &info.unknown {
+                    diagnostics::warning_at(
+                        &span,
+                        format_args!("unknown code block option `{}`", word),
+                    );
+                }
+                for (start, end) in info.lines {
+                    if end > lines {
+                        let range = match start == end {
+                            true => format!("line {}", start),
+                            false => format!("lines {}-{}", start, end),
+                        };
+                        diagnostics::warning_at(
+                            &span,
+                            format_args!(
+                                "code block has {} line{}, so {} can not be highlighted",
+                                lines,
+                                if lines == 1 { "" } else { "s" },
+                                range
+                            ),
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reports references like `[^label]` in the `text` range of the note.
+fn undefined_footnotes(note: &Note, text: Range<usize>) {
+    let mut offset = text.start;
+    while let Some(start) = note.src[offset..text.end].find("[^") {
+        let start = offset + start;
+        let label = &note.src[start + 2..text.end];
+        let Some(len) = label.find(|c: char| c == ']' || c == '[' || c.is_whitespace()) else {
+            break;
+        };
+        let end = start + 2 + len;
+        offset = end;
+        if len > 0 && label[len..].starts_with(']') {
+            diagnostics::warning_at(
+                &Span::new(&note.path, &note.src, start..end + 1),
+                format_args!("footnote `{}` is not defined", &label[..len]),
+            );
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Options of fenced code blocks, written after the language in the info
+//! string: ```` ```rust title="main.rs" {2,4-6} linenos ````.
+
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Info {
+    /// Language the code is highlighted as, the first word.
+    pub lang: Option<String>,
+    /// Caption of the block, usually a file name.
+    pub title: Option<String>,
+    /// Ranges of lines to highlight, 1-based and inclusive.
+    pub lines: Vec<(usize, usize)>,
+    /// Whether the lines are numbered.
+    pub linenos: bool,
+    /// Other `key=value` options, rendered as `data-key` attributes.
+    pub attrs: Vec<(String, String)>,
+    /// Words that are not options we know.
+    #[serde(skip)]
+    pub unknown: Vec<String>,
+}
+
+impl Info {
+    /// Reads the info string of a fenced code block.
+    pub fn parse(info: &str) -> Self {
+        let mut parsed = Info::default();
+        for (i, word) in words(info).into_iter().enumerate() {
+            if let Some(ranges) = word.strip_prefix('{').and_then(|w| w.strip_suffix('}')) {
+                match line_ranges(ranges) {
+                    Some(ranges) => parsed.lines.extend(ranges),
+                    None => parsed.unknown.push(word),
+                }
+                continue;
+            }
+            match word.split_once('=') {
+                Some(("title", title)) => parsed.title = Some(unquote(title).to_string()),
+                Some(("linenos", value)) => parsed.linenos = unquote(value) != "false",
+                Some((key, value)) if !key.is_empty() => parsed
+                    .attrs
+                    .push((key.to_string(), unquote(value).to_string())),
+                None if word == "linenos" => parsed.linenos = true,
+                None if i == 0 => parsed.lang = Some(word),
+                _ => parsed.unknown.push(word),
+            }
+        }
+        parsed
+    }
+
+    /// Whether the 1-based `line` is highlighted.
+    pub fn highlighted(&self, line: usize) -> bool {
+        self.lines
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&line))
+    }
+
+    /// Whether the lines are rendered one by one, to number or highlight
+    /// them.
+    pub fn by_line(&self) -> bool {
+        self.linenos || !self.lines.is_empty()
+    }
+}
+
+/// Splits the info string at spaces outside of quotes and braces.
+fn words(info: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quote = None;
+    let mut braces = false;
+    for c in info.chars() {
+        match c {
+            '"' | '\'' if quote == Some(c) => quote = None,
+            '"' | '\'' if quote.is_none() && !braces => quote = Some(c),
+            '{' if quote.is_none() => braces = true,
+            '}' if quote.is_none() => braces = false,
+            c if c.is_whitespace() && quote.is_none() && !braces => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(value) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return value;
+        }
+    }
+    value
+}
+
+/// Reads `2,4-6` into `(2, 2)` and `(4, 6)`.
+fn line_ranges(ranges: &str) -> Option<Vec<(usize, usize)>> {
+    ranges
+        .split(',')
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+            (start >= 1 && end >= start).then_some((start, end))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options() {
+        let info = Info::parse(r#"rust title="a b" {2,4-6} linenos"#);
+        assert_eq!(info.lang.as_deref(), Some("rust"));
+        assert_eq!(info.title.as_deref(), Some("a b"));
+        assert_eq!(info.lines, vec![(2, 2), (4, 6)]);
+        assert!(info.linenos);
+        assert!(info.unknown.is_empty());
+        assert!(info.highlighted(5) && !info.highlighted(3));
+    }
+
+    #[test]
+    fn options_without_lang() {
+        let info = Info::parse(r#"title="a b" {2,4-6} linenos"#);
+        assert_eq!(info.lang, None);
+        assert_eq!(info.title.as_deref(), Some("a b"));
+        assert_eq!(info.lines, vec![(2, 2), (4, 6)]);
+    }
+
+    #[test]
+    fn invalid_ranges_are_unknown() {
+        let info = Info::parse("sh {r}");
+        assert!(info.lines.is_empty());
+        assert_eq!(info.unknown, vec!["{r}"]);
+        assert_eq!(Info::parse("sh {3-1}").unknown, vec!["{3-1}"]);
+        assert_eq!(Info::parse("sh {0}").unknown, vec!["{0}"]);
+    }
+
+    #[test]
+    fn empty_ranges() {
+        let info = Info::parse("sh {}");
+        assert!(info.lines.is_empty() && info.unknown.is_empty());
+        assert!(!info.by_line());
+    }
+
+    #[test]
+    fn attributes() {
+        let info = Info::parse("py linenos=false data='x y'");
+        assert!(!info.linenos);
+        assert_eq!(info.attrs, vec![("data".to_string(), "x y".to_string())]);
+    }
+}
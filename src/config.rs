@@ -0,0 +1,149 @@
+//! Project configuration, read from `cheatsheet.toml` and overridden by the
+//! flags on the command line.
+
+use std::path::{Path, PathBuf};
+
+use glob::Pattern;
+use serde::{Deserialize, Deserializer};
+
+use crate::assets::Layout;
+use crate::error::Error;
+use crate::extensions::Extensions;
+use crate::highlight;
+use crate::parser::Parser;
+use crate::pdf::PageSize;
+use crate::split::{Preface, Split};
+use crate::Format;
+
+/// File the configuration is read from if no other one is given.
+pub const FILE: &str = "cheatsheet.toml";
+
+#[derive(Deserialize, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Markdown files, directories to search for `*.md` files, or glob
+    /// patterns.
+    pub inputs: Vec<PathBuf>,
+    /// Only use files found in directories or by globs that match one of
+    /// these patterns.
+    #[serde(deserialize_with = "patterns")]
+    pub include: Vec<Pattern>,
+    /// Skip files found in directories or by globs that match one of these
+    /// patterns.
+    #[serde(deserialize_with = "patterns")]
+    pub exclude: Vec<Pattern>,
+    /// Folder with the templates and their stylesheets.
+    #[serde(alias = "theme")]
+    pub templates: PathBuf,
+    pub out: PathBuf,
+    /// Root of the Obsidian vault, defaults to the common parent of the inputs.
+    pub vault: Option<PathBuf>,
+    /// Folder `![[embeds]]` are looked up in, relative to the vault root.
+    pub attachments: Option<PathBuf>,
+    pub split: Split,
+    pub preface: Preface,
+    /// Outputs to write, every one at most once.
+    pub formats: Vec<Format>,
+    /// Markdown extensions to parse the notes with, or the ones to turn on or
+    /// off from the defaults.
+    pub extensions: Extensions,
+    /// Parser the notes are read with.
+    pub parser: Parser,
+    /// How linked files and images are laid out in the output.
+    pub assets: Layout,
+    /// Bundled syntect theme code blocks are highlighted with.
+    pub code_theme: String,
+    /// Write a single `index.html` with its stylesheets and assets in it.
+    pub standalone: bool,
+    pub pdf: Pdf,
+    /// Variables passed on to the templates as `vars`.
+    pub vars: tera::Map<String, tera::Value>,
+}
+
+/// Layout of the PDF.
+#[derive(Deserialize, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Pdf {
+    pub page_size: PageSize,
+    /// Whether the pages are portrait instead of landscape.
+    pub portrait: bool,
+    pub columns: usize,
+    /// Number of pages the text is shrunk to fit on, if any.
+    pub pages: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            inputs: vec![],
+            include: vec![],
+            exclude: vec![],
+            templates: PathBuf::from("data/templates/"),
+            out: PathBuf::from("out"),
+            vault: None,
+            attachments: None,
+            split: Split::H1,
+            preface: Preface::Block,
+            formats: vec![Format::Html],
+            extensions: Extensions::default(),
+            parser: Parser::default(),
+            assets: Layout::default(),
+            code_theme: highlight::DEFAULT_THEME.to_string(),
+            standalone: false,
+            pdf: Pdf::default(),
+            vars: tera::Map::new(),
+        }
+    }
+}
+
+impl Default for Pdf {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::A4,
+            portrait: false,
+            columns: 3,
+            pages: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration from `path`. Paths in it are relative to the
+    /// folder of the file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let toml = std::fs::read_to_string(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut config = toml::from_str::<Config>(&toml).map_err(|source| Error::Config {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        if !dir.as_os_str().is_empty() {
+            let rebase = |path: &mut PathBuf| *path = dir.join(&*path);
+            config.inputs.iter_mut().for_each(rebase);
+            rebase(&mut config.templates);
+            rebase(&mut config.out);
+            if let Some(vault) = &mut config.vault {
+                rebase(vault);
+            }
+            let dir = Pattern::escape(&dir.to_string_lossy());
+            for pattern in config.include.iter_mut().chain(&mut config.exclude) {
+                *pattern = Pattern::new(&format!("{}/{}", dir, pattern.as_str())).unwrap();
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn patterns<'de, D>(deserializer: D) -> Result<Vec<Pattern>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| Pattern::new(pattern).map_err(serde::de::Error::custom))
+        .collect()
+}
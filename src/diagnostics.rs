@@ -0,0 +1,193 @@
+//! Warnings and errors printed while building.
+//!
+//! Messages about a place in a note are printed the way rustc does, with the
+//! line of the note they are about.
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+thread_local! {
+    /// Messages printed since `capture` was called, if it was.
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Number of errors and warnings printed since the build started.
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Place in the source of a note.
+#[derive(Debug, Clone)]
+pub struct Span<'a> {
+    pub path: &'a Path,
+    pub src: &'a str,
+    /// Byte range in `src`.
+    pub range: Range<usize>,
+}
+
+impl<'a> Span<'a> {
+    pub fn new(path: &'a Path, src: &'a str, range: Range<usize>) -> Self {
+        Self { path, src, range }
+    }
+
+    /// Line and column, both starting at 1, of the start of the span.
+    pub fn line_column(&self) -> (usize, usize) {
+        let start = self.start();
+        let before = &self.src[..start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (line, before[line_start..].chars().count() + 1)
+    }
+
+    /// Start of the span, moved back to a character boundary if needed.
+    fn start(&self) -> usize {
+        let mut start = self.range.start.min(self.src.len());
+        while !self.src.is_char_boundary(start) {
+            start -= 1;
+        }
+        start
+    }
+
+    /// Renders the location and the line the span starts on, with the span
+    /// underlined up to the end of that line.
+    fn snippet(&self) -> String {
+        let (line, column) = self.line_column();
+        let start = self.start();
+        let line_start = self.src[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.src[start..]
+            .find('\n')
+            .map_or(self.src.len(), |i| start + i);
+        let text = self.src[line_start..line_end].trim_end_matches('\r');
+        // Spans starting on the `\r` of a line break are marked at its end
+        let start = start.min(line_start + text.len());
+        let end = self.range.end.clamp(start, line_start + text.len());
+
+        // Tabs are kept so the marks line up with the text above
+        let indent = self.src[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let width = self
+            .src
+            .get(start..end)
+            .map_or(0, |text| text.chars().count());
+        let marks = "^".repeat(width.max(1));
+        let gutter = " ".repeat(line.to_string().len());
+        format!(
+            "{gutter}--> {}:{}:{}\n{gutter} |\n{} | {}\n{gutter} | {}{}",
+            self.path.display(),
+            line,
+            column,
+            line,
+            text,
+            indent,
+            marks
+        )
+    }
+}
+
+pub fn warning(message: impl Display) {
+    emit(format!("warning: {}", message));
+}
+
+pub fn error(message: impl Display) {
+    emit(format!("error: {}", message));
+}
+
+/// Prints a warning about a place in a note.
+pub fn warning_at(span: &Span, message: impl Display) {
+    emit(format!("warning: {}\n{}", message, span.snippet()));
+}
+
+/// Prints an error about a place in a note.
+pub fn error_at(span: &Span, message: impl Display) {
+    emit(format!("error: {}\n{}", message, span.snippet()));
+}
+
+/// Prints an error that stopped the build, with the errors that caused it.
+pub fn report(err: &dyn Error) {
+    let mut message = err.to_string().trim().to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message += &format!(": {}", err.to_string().trim());
+        source = err.source();
+    }
+    error(message);
+}
+
+/// Starts counting errors and warnings from 0 again, for the next build.
+pub fn reset() {
+    ERRORS.store(0, Ordering::Relaxed);
+    WARNINGS.store(0, Ordering::Relaxed);
+}
+
+/// Number of errors printed so far, including ones printed again.
+pub fn errors() -> usize {
+    ERRORS.load(Ordering::Relaxed)
+}
+
+/// Number of warnings printed so far, including ones printed again.
+pub fn warnings() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
+fn emit(message: String) {
+    if message.starts_with("error") {
+        ERRORS.fetch_add(1, Ordering::Relaxed);
+    } else {
+        WARNINGS.fetch_add(1, Ordering::Relaxed);
+    }
+    eprintln!("{}", message);
+    CAPTURED.with_borrow_mut(|captured| {
+        if let Some(captured) = captured {
+            captured.push(message);
+        }
+    });
+}
+
+/// Runs `f` and returns the messages it printed, so they can be printed again
+/// when its result is reused.
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let outer = CAPTURED.replace(Some(vec![]));
+    let result = f();
+    let captured = CAPTURED.replace(outer).unwrap_or_default();
+    (result, captured)
+}
+
+/// Prints messages returned by `capture` again.
+pub fn replay(messages: &[String]) {
+    for message in messages {
+        emit(message.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippet() {
+        let src = "# A\nsome text\n";
+        let span = Span::new(Path::new("a.md"), src, 9..13);
+        assert_eq!(span.line_column(), (2, 6));
+        assert_eq!(
+            span.snippet(),
+            " --> a.md:2:6\n  |\n2 | some text\n  |      ^^^^"
+        );
+    }
+
+    #[test]
+    fn snippet_of_crlf_line() {
+        let src = "---\r\ntitle: \"x\r\n---\r\n# A\r\n";
+        let span = Span::new(Path::new("a.md"), src, 14..20);
+        assert_eq!(
+            span.snippet(),
+            " --> a.md:2:10\n  |\n2 | title: \"x\n  |          ^"
+        );
+        let span = Span::new(Path::new("a.md"), src, 3..9);
+        assert!(span.snippet().ends_with("1 | ---\n  |    ^"));
+    }
+}
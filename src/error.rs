@@ -0,0 +1,126 @@
+//! Errors that stop a build, and the exit status they end the program with.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::highlight;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The configuration file is not valid.
+    Config {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// None of the inputs is a note.
+    NoInput,
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Write {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Copying the stylesheets of the templates failed.
+    Stylesheets(fs_extra::error::Error),
+    /// There is no bundled theme to highlight code with by this name.
+    Theme(String),
+    /// The templates could not be loaded or rendered.
+    Template(tera::Error),
+    /// The templates are in a folder whose path is not valid UTF-8, which
+    /// Tera cannot load them from.
+    Templates(PathBuf),
+    /// The inputs could not be watched for changes.
+    Watch(notify_debouncer_full::notify::Error),
+    /// The output could not be served.
+    Serve(Box<dyn std::error::Error + Send + Sync>),
+    /// The build finished and wrote the output, but errors were reported
+    /// along the way.
+    Reported(usize),
+    /// Checking found warnings or errors.
+    Problems(usize),
+}
+
+impl Error {
+    /// Exit status for the error, following `sysexits.h`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Reported(_) | Error::Problems(_) => 65,
+            Error::NoInput | Error::Read { .. } => 66,
+            Error::Watch(_) | Error::Serve(_) => 69,
+            Error::Write { .. } | Error::Stylesheets(_) => 73,
+            Error::Config { .. } | Error::Theme(_) | Error::Template(_) | Error::Templates(_) => 78,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config { path, .. } => write!(f, "invalid configuration in {}", path.display()),
+            Error::NoInput => write!(f, "no notes to build from"),
+            Error::Read { path, .. } => write!(f, "could not read {}", path.display()),
+            Error::Write { path, .. } => write!(f, "could not write {}", path.display()),
+            Error::Stylesheets(_) => write!(f, "could not copy the stylesheets"),
+            Error::Theme(name) => write!(
+                f,
+                "no code theme called `{}`, there are {}",
+                name,
+                highlight::Theme::names()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Error::Template(_) => write!(f, "could not render the templates"),
+            Error::Templates(path) => write!(
+                f,
+                "could not load the templates from {}, the path is not valid UTF-8",
+                path.display()
+            ),
+            Error::Watch(_) => write!(f, "could not watch the inputs"),
+            Error::Serve(_) => write!(f, "could not serve the output"),
+            Error::Reported(1) => write!(f, "built with 1 error"),
+            Error::Reported(errors) => write!(f, "built with {} errors", errors),
+            Error::Problems(1) => write!(f, "found 1 problem"),
+            Error::Problems(problems) => write!(f, "found {} problems", problems),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Read { source, .. } | Error::Write { source, .. } => Some(source),
+            Error::Config { source, .. } => Some(source),
+            Error::Stylesheets(err) => Some(err),
+            Error::Template(err) => Some(err),
+            Error::Watch(err) => Some(err),
+            Error::Serve(err) => Some(err.as_ref()),
+            Error::NoInput
+            | Error::Theme(_)
+            | Error::Templates(_)
+            | Error::Reported(_)
+            | Error::Problems(_) => None,
+        }
+    }
+}
+
+impl From<tera::Error> for Error {
+    fn from(err: tera::Error) -> Self {
+        Error::Template(err)
+    }
+}
+
+impl From<fs_extra::error::Error> for Error {
+    fn from(err: fs_extra::error::Error) -> Self {
+        Error::Stylesheets(err)
+    }
+}
+
+impl From<notify_debouncer_full::notify::Error> for Error {
+    fn from(err: notify_debouncer_full::notify::Error) -> Self {
+        Error::Watch(err)
+    }
+}
@@ -0,0 +1,152 @@
+//! Markdown extensions of pulldown-cmark that can be turned on and off.
+
+use std::collections::BTreeMap;
+
+use pulldown_cmark as md;
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    clap::ValueEnum,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Extension {
+    /// `| tables |`.
+    Tables,
+    /// `[^footnotes]`, defined anywhere in the note.
+    Footnotes,
+    /// Footnotes the way pulldown-cmark used to parse them, instead of GFM's.
+    OldFootnotes,
+    /// `~~strikethrough~~`.
+    Strikethrough,
+    /// `- [x] task lists`.
+    Tasklists,
+    /// Curly quotes, dashes and ellipses.
+    SmartPunctuation,
+    /// `# Heading {#id .class key=value}`.
+    HeadingAttributes,
+    /// `---` YAML front matter.
+    YamlMetadata,
+    /// `+++` TOML front matter.
+    PlusesMetadata,
+    /// `$inline$` and `$$display$$` math.
+    Math,
+    /// GitHub flavored markdown, like `> [!NOTE]` alerts.
+    Gfm,
+    /// `term` followed by `: definition`.
+    DefinitionList,
+}
+
+/// Extensions used unless configured otherwise.
+pub const DEFAULT: &[Extension] = &[
+    Extension::Math,
+    Extension::Tables,
+    Extension::Gfm,
+    Extension::Footnotes,
+    Extension::Strikethrough,
+    Extension::SmartPunctuation,
+    Extension::PlusesMetadata,
+    Extension::YamlMetadata,
+    Extension::DefinitionList,
+    Extension::HeadingAttributes,
+];
+
+impl Extension {
+    pub fn option(self) -> md::Options {
+        match self {
+            Extension::Tables => md::Options::ENABLE_TABLES,
+            Extension::Footnotes => md::Options::ENABLE_FOOTNOTES,
+            Extension::OldFootnotes => md::Options::ENABLE_OLD_FOOTNOTES,
+            Extension::Strikethrough => md::Options::ENABLE_STRIKETHROUGH,
+            Extension::Tasklists => md::Options::ENABLE_TASKLISTS,
+            Extension::SmartPunctuation => md::Options::ENABLE_SMART_PUNCTUATION,
+            Extension::HeadingAttributes => md::Options::ENABLE_HEADING_ATTRIBUTES,
+            Extension::YamlMetadata => md::Options::ENABLE_YAML_STYLE_METADATA_BLOCKS,
+            Extension::PlusesMetadata => md::Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS,
+            Extension::Math => md::Options::ENABLE_MATH,
+            Extension::Gfm => md::Options::ENABLE_GFM,
+            Extension::DefinitionList => md::Options::ENABLE_DEFINITION_LIST,
+        }
+    }
+}
+
+/// Parser options with the extensions enabled.
+pub fn options(extensions: &[Extension]) -> md::Options {
+    extensions
+        .iter()
+        .fold(md::Options::empty(), |options, extension| {
+            options | extension.option()
+        })
+}
+
+/// Turns `extension` on or off in `options`.
+pub fn toggle(options: &mut md::Options, extension: Extension, on: bool) {
+    match (extension, on) {
+        (_, true) => options.insert(extension.option()),
+        // Old footnotes are footnotes parsed differently, turning them off
+        // keeps the footnotes
+        (Extension::OldFootnotes, false) => {
+            let footnotes = options.contains(md::Options::ENABLE_FOOTNOTES);
+            options.remove(md::Options::ENABLE_OLD_FOOTNOTES);
+            options.set(md::Options::ENABLE_FOOTNOTES, footnotes);
+        }
+        (Extension::Footnotes, false) => options.remove(md::Options::ENABLE_OLD_FOOTNOTES),
+        (_, false) => options.remove(extension.option()),
+    }
+}
+
+/// Extensions as given in the configuration or front matter, either a list
+/// of all of them, `["tables", "math"]`, or a table turning some on or off,
+/// `{ smart-punctuation = false }`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Extensions {
+    Only(Vec<Extension>),
+    Toggle(BTreeMap<Extension, bool>),
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Extensions::Only(DEFAULT.to_vec())
+    }
+}
+
+impl Extensions {
+    /// Parser options with the extensions, turning them on or off in `base`
+    /// if only some are given.
+    pub fn options(&self, base: md::Options) -> md::Options {
+        match self {
+            Extensions::Only(extensions) => options(extensions),
+            Extensions::Toggle(toggles) => {
+                let mut options = base;
+                for (&extension, &on) in toggles {
+                    toggle(&mut options, extension, on);
+                }
+                options
+            }
+        }
+    }
+
+    pub fn toggle(&mut self, extension: Extension, on: bool) {
+        match self {
+            Extensions::Only(extensions) if on => {
+                if !extensions.contains(&extension) {
+                    extensions.push(extension);
+                }
+            }
+            Extensions::Only(extensions) => extensions.retain(|other| *other != extension),
+            Extensions::Toggle(toggles) => {
+                toggles.insert(extension, on);
+            }
+        }
+    }
+}
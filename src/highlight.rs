@@ -0,0 +1,133 @@
+//! Syntax highlighting of code blocks at build time, with the syntaxes and
+//! themes of syntect that are bundled in the binary.
+//!
+//! Code is split into tokens with the scopes they are in. The HTML marks them
+//! with classes styled by the stylesheet of a theme, other outputs look up
+//! their colors in the theme.
+
+use std::sync::LazyLock;
+
+use syntect::highlighting::{self, Highlighter, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, ClassStyle};
+use syntect::parsing::{ParseState, Scope, ScopeStack, ScopeStackOp, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Theme code is highlighted with if no other one is configured.
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+
+/// Prefix of the classes of highlighted code, `hl-keyword`.
+const PREFIX: &str = "hl-";
+
+/// Class of highlighted code blocks, which the theme gives its colors.
+pub const CODE_CLASS: &str = "hl-code";
+
+/// Path of the stylesheet of the theme, relative to the output directory.
+pub const STYLESHEET: &str = "css/highlight.css";
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Run of code in the same scopes.
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub text: String,
+    /// Scopes the text is in, outermost first.
+    pub scopes: Vec<Scope>,
+}
+
+/// Splits `code` into tokens by the syntax of `lang`, a name or file
+/// extension like `rust` or `rs`. Returns `None` if there is no syntax for
+/// it.
+pub fn tokens(code: &str, lang: &str) -> Option<Vec<Token>> {
+    let syntax = SYNTAXES.find_syntax_by_token(lang)?;
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut tokens: Vec<Token> = vec![];
+    for line in LinesWithEndings::from(code) {
+        let ops = state.parse_line(line, &SYNTAXES).ok()?;
+        let mut start = 0;
+        let end = std::iter::once((line.len(), ScopeStackOp::Noop));
+        for (offset, op) in ops.into_iter().chain(end) {
+            if offset > start {
+                let text = &line[start..offset];
+                match tokens.last_mut() {
+                    Some(last) if last.scopes == stack.as_slice() => last.text.push_str(text),
+                    _ => tokens.push(Token {
+                        text: text.to_string(),
+                        scopes: stack.as_slice().to_vec(),
+                    }),
+                }
+                start = offset;
+            }
+            stack.apply(&op).ok()?;
+        }
+    }
+    Some(tokens)
+}
+
+/// Splits tokens into the lines of the code, each ending with its line
+/// break.
+pub fn lines(tokens: Vec<Token>) -> Vec<Vec<Token>> {
+    let mut lines = vec![vec![]];
+    for token in tokens {
+        for text in token.text.split_inclusive('\n') {
+            lines.last_mut().unwrap().push(Token {
+                text: text.to_string(),
+                scopes: token.scopes.clone(),
+            });
+            if text.ends_with('\n') {
+                lines.push(vec![]);
+            }
+        }
+    }
+    if lines.last().is_some_and(Vec::is_empty) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Classes of the HTML element marking `scope`, `hl-keyword hl-control` for
+/// `keyword.control`.
+pub fn classes(scope: Scope) -> String {
+    scope
+        .build_string()
+        .split('.')
+        .map(|atom| format!("{}{}", PREFIX, atom))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Theme the colors of tokens are looked up in.
+pub struct Theme {
+    theme: &'static highlighting::Theme,
+    highlighter: Highlighter<'static>,
+}
+
+impl Theme {
+    /// The bundled theme called `name`, if there is one.
+    pub fn new(name: &str) -> Option<Self> {
+        let theme = THEMES.themes.get(name)?;
+        Some(Self {
+            theme,
+            highlighter: Highlighter::new(theme),
+        })
+    }
+
+    /// Names of the bundled themes.
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        THEMES.themes.keys().map(String::as_str)
+    }
+
+    /// Color of text in the `scopes`, as RGB values from 0 to 1.
+    pub fn color(&self, scopes: &[Scope]) -> [f32; 3] {
+        let color = self.highlighter.style_for_stack(scopes).foreground;
+        [color.r, color.g, color.b].map(|channel| channel as f32 / 255.0)
+    }
+
+    /// Stylesheet giving the classes of highlighted HTML the colors of the
+    /// theme.
+    pub fn stylesheet(&self) -> String {
+        css_for_theme_with_class_style(self.theme, ClassStyle::SpacedPrefixed { prefix: PREFIX })
+            .unwrap_or_default()
+    }
+}
@@ -22,7 +22,10 @@
 
 use std::collections::HashMap;
 
-use crate::Event::*;
+use crate::code;
+use crate::highlight::{self, Token};
+use crate::math;
+use pulldown_cmark::Event::*;
 use pulldown_cmark::{
     Alignment, BlockQuoteKind, CodeBlockKind, CowStr, Event, LinkType, Tag, TagEnd,
 };
@@ -48,6 +51,10 @@ struct HtmlWriter<'a, I, W> {
     /// Whether if inside a metadata block (text should not be written)
     in_non_writing_block: bool,
 
+    /// Options and source of the fenced code block being read, which is
+    /// highlighted at its end.
+    code: Option<(code::Info, String)>,
+
     table_state: TableState,
     table_alignments: Vec<Alignment>,
     table_cell_index: usize,
@@ -65,6 +72,7 @@ where
             writer,
             end_newline: true,
             in_non_writing_block: false,
+            code: None,
             table_state: TableState::Head,
             table_alignments: vec![],
             table_cell_index: 0,
@@ -90,6 +98,108 @@ where
         Ok(())
     }
 
+    /// Writes math as MathML, or the TeX source marked as an error if it
+    /// cannot be rendered, which is reported before rendering.
+    fn write_math(&mut self, tex: &str, display: bool) -> Result<(), W::Error> {
+        match math::to_mathml(tex, display) {
+            Ok(mathml) => self.write(&mathml),
+            Err(_) => {
+                self.write(r#"<code class="math-error">"#)?;
+                escape_html(&mut self.writer, tex)?;
+                self.write("</code>")
+            }
+        }
+    }
+
+    /// Writes a fenced code block, highlighted if there is a syntax for its
+    /// language, with its title and its lines numbered or highlighted if the
+    /// info string asks for it.
+    fn write_code(&mut self, info: &code::Info, code: &str) -> Result<(), W::Error> {
+        if let Some(title) = &info.title {
+            self.write("<figure class=\"code\"><figcaption>")?;
+            escape_html_body_text(&mut self.writer, title)?;
+            self.write("</figcaption>")?;
+        }
+        self.write("<pre")?;
+        if info.linenos {
+            self.write(" class=\"linenos\"")?;
+        }
+        for (key, value) in &info.attrs {
+            self.write(" data-")?;
+            escape_html(&mut self.writer, key)?;
+            self.write("=\"")?;
+            escape_html(&mut self.writer, value)?;
+            self.write("\"")?;
+        }
+        self.write("><code")?;
+        let tokens = info
+            .lang
+            .as_deref()
+            .and_then(|lang| highlight::tokens(code, lang));
+        if let Some(lang) = &info.lang {
+            self.write(" class=\"language-")?;
+            escape_html(&mut self.writer, lang)?;
+            if tokens.is_some() {
+                self.write(" ")?;
+                self.write(highlight::CODE_CLASS)?;
+            }
+            self.write("\"")?;
+        }
+        self.write(">")?;
+
+        let tokens = tokens.unwrap_or_else(|| {
+            vec![Token {
+                text: code.to_string(),
+                scopes: vec![],
+            }]
+        });
+        if info.by_line() {
+            for (i, line) in highlight::lines(tokens).iter().enumerate() {
+                if info.highlighted(i + 1) {
+                    self.write("<span class=\"line highlighted\">")?;
+                } else {
+                    self.write("<span class=\"line\">")?;
+                }
+                self.write_tokens(line)?;
+                self.write("</span>")?;
+            }
+        } else {
+            self.write_tokens(&tokens)?;
+        }
+        self.write("</code></pre>")?;
+        if info.title.is_some() {
+            self.write("</figure>")?;
+        }
+        self.write_newline()
+    }
+
+    /// Writes tokens in nested spans, one for every scope, so the selectors
+    /// of the theme stylesheet match them.
+    fn write_tokens(&mut self, tokens: &[Token]) -> Result<(), W::Error> {
+        let mut open: &[_] = &[];
+        for token in tokens {
+            let common = open
+                .iter()
+                .zip(&token.scopes)
+                .take_while(|(open, scope)| open == scope)
+                .count();
+            for _ in common..open.len() {
+                self.write("</span>")?;
+            }
+            for scope in &token.scopes[common..] {
+                self.write("<span class=\"")?;
+                self.write(&highlight::classes(*scope))?;
+                self.write("\">")?;
+            }
+            escape_html_body_text(&mut self.writer, &token.text)?;
+            open = &token.scopes;
+        }
+        for _ in open {
+            self.write("</span>")?;
+        }
+        Ok(())
+    }
+
     fn run(mut self) -> Result<(), W::Error> {
         while let Some(event) = self.iter.next() {
             match event {
@@ -100,7 +210,9 @@ where
                     self.end_tag(tag)?;
                 }
                 Text(text) => {
-                    if !self.in_non_writing_block {
+                    if let Some((_, code)) = &mut self.code {
+                        code.push_str(&text);
+                    } else if !self.in_non_writing_block {
                         escape_html_body_text(&mut self.writer, &text)?;
                         self.end_newline = text.ends_with('\n');
                     }
@@ -112,12 +224,12 @@ where
                 }
                 InlineMath(text) => {
                     self.write(r#"<span class="math math-inline">"#)?;
-                    escape_html(&mut self.writer, &text)?;
+                    self.write_math(&text, false)?;
                     self.write("</span>")?;
                 }
                 DisplayMath(text) => {
                     self.write(r#"<span class="math math-display">"#)?;
-                    escape_html(&mut self.writer, &text)?;
+                    self.write_math(&text, true)?;
                     self.write("</span>")?;
                 }
                 Html(html) | InlineHtml(html) => {
@@ -252,29 +364,24 @@ where
                 } else {
                     self.write(&format!("\n<blockquote{}>\n", class_str))?;
                 }
-                match kind {
-                    Some(kind) => {
-                        let title_str = match kind {
-                            BlockQuoteKind::Note => {
-                                r#"<p class = "markdown-alert-title-note">Note</p>"#
-                            }
-                            BlockQuoteKind::Tip => {
-                                r#"<p class = "markdown-alert-title-tip">Tip</p>"#
-                            }
-                            BlockQuoteKind::Important => {
-                                r#"<p class = "markdown-alert-title-important">Important</p>"#
-                            }
-                            BlockQuoteKind::Warning => {
-                                r#"<p class = "markdown-alert-title-warning">Warning</p>"#
-                            }
-                            BlockQuoteKind::Caution => {
-                                r#"<p class = "markdown-alert-title-caution">Caution</p>"#
-                            }
-                        };
-                        self.write(&title_str)?;
-                    }
-                    None => {}
-                };
+                if let Some(kind) = kind {
+                    let title_str = match kind {
+                        BlockQuoteKind::Note => {
+                            r#"<p class = "markdown-alert-title-note">Note</p>"#
+                        }
+                        BlockQuoteKind::Tip => r#"<p class = "markdown-alert-title-tip">Tip</p>"#,
+                        BlockQuoteKind::Important => {
+                            r#"<p class = "markdown-alert-title-important">Important</p>"#
+                        }
+                        BlockQuoteKind::Warning => {
+                            r#"<p class = "markdown-alert-title-warning">Warning</p>"#
+                        }
+                        BlockQuoteKind::Caution => {
+                            r#"<p class = "markdown-alert-title-caution">Caution</p>"#
+                        }
+                    };
+                    self.write(title_str)?;
+                }
                 Ok(())
             }
             Tag::CodeBlock(info) => {
@@ -283,14 +390,9 @@ where
                 }
                 match info {
                     CodeBlockKind::Fenced(info) => {
-                        let lang = info.split(' ').next().unwrap();
-                        if lang.is_empty() {
-                            self.write("<pre><code>")
-                        } else {
-                            self.write("<pre><code class=\"language-")?;
-                            escape_html(&mut self.writer, lang)?;
-                            self.write("\">")
-                        }
+                        // Written once all of the code is known
+                        self.code = Some((code::Info::parse(&info), String::new()));
+                        Ok(())
                     }
                     CodeBlockKind::Indented => self.write("<pre><code>"),
                 }
@@ -448,9 +550,10 @@ where
             TagEnd::BlockQuote(_) => {
                 self.write("</blockquote>\n")?;
             }
-            TagEnd::CodeBlock => {
-                self.write("</code></pre>\n")?;
-            }
+            TagEnd::CodeBlock => match self.code.take() {
+                Some((info, code)) => self.write_code(&info, &code)?,
+                None => self.write("</code></pre>\n")?,
+            },
             TagEnd::List(true) => {
                 self.write("</ol>\n")?;
             }
@@ -604,6 +707,7 @@ where
 /// </ul>
 /// "#);
 /// ```
+#[allow(dead_code)]
 pub fn write_html_io<'a, I, W>(writer: W, iter: I) -> std::io::Result<()>
 where
     I: Iterator<Item = Event<'a>>,
@@ -0,0 +1,177 @@
+//! Expansion of the input arguments into markdown files.
+
+use std::path::{Component, Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+
+use crate::diagnostics;
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: false,
+    require_literal_leading_dot: true,
+};
+
+/// Expands directories (recursively, to the `*.md` files in them) and glob
+/// patterns into files.
+///
+/// Files are ordered by argument, and by path within the files an argument
+/// expands to. The `include` and `exclude` patterns only filter files found
+/// through directories and globs, files given explicitly are always used.
+/// Hidden files and folders are skipped. Files that are listed more than once
+/// are only used the first time.
+pub fn expand(inputs: &[PathBuf], include: &[Pattern], exclude: &[Pattern]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = vec![];
+
+    for input in inputs {
+        let mut expanded = if input.is_file() {
+            vec![input.clone()]
+        } else if !input.is_dir() && !is_pattern(input) {
+            diagnostics::error(format_args!("input `{}` does not exist", input.display()));
+            continue;
+        } else {
+            let (pattern, root) = if input.is_dir() {
+                let pattern = Pattern::escape(&input.to_string_lossy()) + "/**/*.md";
+                (pattern, input.clone())
+            } else {
+                (input.to_string_lossy().into_owned(), pattern_root(input))
+            };
+            let paths = match glob::glob_with(&pattern, MATCH_OPTIONS) {
+                Ok(paths) => paths,
+                Err(err) => {
+                    diagnostics::warning(format_args!(
+                        "`{}` is not a valid pattern: {}",
+                        input.display(),
+                        err
+                    ));
+                    continue;
+                }
+            };
+            let mut matches = paths
+                .filter_map(Result::ok)
+                .filter(|path| {
+                    path.is_file() && !is_hidden(path, &root) && is_included(path, include, exclude)
+                })
+                .collect::<Vec<_>>();
+            if matches.is_empty() {
+                diagnostics::warning(format_args!(
+                    "`{}` did not match any files",
+                    input.display()
+                ));
+            }
+            matches.sort();
+            matches
+        };
+        expanded.retain(|path| !files.contains(path));
+        files.extend(expanded);
+    }
+
+    files
+}
+
+/// Whether the input is a glob pattern rather than a path.
+pub fn is_pattern(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+fn is_included(path: &Path, include: &[Pattern], exclude: &[Pattern]) -> bool {
+    let matches = |pattern: &Pattern| pattern.matches_path_with(path, MATCH_OPTIONS);
+    (include.is_empty() || include.iter().any(matches)) && !exclude.iter().any(matches)
+}
+
+/// Folder a glob pattern matches files in, the components before the first
+/// one with a wildcard.
+fn pattern_root(pattern: &Path) -> PathBuf {
+    pattern
+        .components()
+        .take_while(|component| !is_pattern(Path::new(component.as_os_str())))
+        .collect()
+}
+
+/// Whether the path is inside a hidden folder like `.obsidian` or `.trash`
+/// below `root`, which `**` in glob patterns does not skip. The folders the
+/// input is in may be hidden.
+fn is_hidden(path: &Path, root: &Path) -> bool {
+    // Glob leaves out the `./` the input may start with
+    let without_cur_dir = |path: &Path| {
+        path.components()
+            .filter(|component| *component != Component::CurDir)
+            .collect::<PathBuf>()
+    };
+    let path = without_cur_dir(path);
+    let path = path.strip_prefix(without_cur_dir(root)).unwrap_or(&path);
+    path.components().any(|component| match component {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Creates the files in a temporary folder, which is returned with the
+    /// files and folders in it.
+    fn vault(files: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for file in files {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "# A\n").unwrap();
+        }
+        dir
+    }
+
+    fn names(files: Vec<PathBuf>, root: &Path) -> Vec<String> {
+        files
+            .iter()
+            .map(|path| {
+                let path = path.strip_prefix(root).unwrap();
+                path.to_string_lossy().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn directory() {
+        let dir = vault(&[
+            "b.md",
+            "a/c.md",
+            "a/d.txt",
+            ".obsidian/e.md",
+            "a/.trash/f.md",
+        ]);
+        let files = expand(&[dir.path().to_path_buf()], &[], &[]);
+        assert_eq!(names(files, dir.path()), vec!["a/c.md", "b.md"]);
+    }
+
+    #[test]
+    fn glob() {
+        let dir = vault(&["a/b.md", "a/c.md", "a/.d.md", "e.md"]);
+        let pattern = dir.path().join("a/*.md");
+        let files = expand(&[pattern], &[], &[]);
+        assert_eq!(names(files, dir.path()), vec!["a/b.md", "a/c.md"]);
+    }
+
+    #[test]
+    fn hidden_parent() {
+        let dir = vault(&[".vault/notes/a.md", ".vault/notes/.trash/b.md"]);
+        let root = dir.path().join(".vault/notes");
+        let files = expand(std::slice::from_ref(&root), &[], &[]);
+        assert_eq!(names(files, dir.path()), vec![".vault/notes/a.md"]);
+
+        let pattern = dir.path().join(".vault/**/*.md");
+        let files = expand(&[pattern], &[], &[]);
+        assert_eq!(names(files, dir.path()), vec![".vault/notes/a.md"]);
+    }
+
+    #[test]
+    fn files_once_in_argument_order() {
+        let dir = vault(&["a.md", "b.md"]);
+        let b = dir.path().join("b.md");
+        let files = expand(&[b.clone(), dir.path().to_path_buf()], &[], &[]);
+        assert_eq!(names(files, dir.path()), vec!["b.md", "a.md"]);
+    }
+}
@@ -0,0 +1,148 @@
+//! Classification of link destinations.
+
+use std::path::{Path, PathBuf};
+
+use percent_encoding::percent_decode_str;
+
+use crate::vault::{self, Vault};
+
+/// Where a link points to.
+#[derive(Debug)]
+pub enum Destination {
+    /// URL with a scheme, e.g. `https://` or `mailto:`, left untouched.
+    External,
+    /// `data:` URI, which contains the file itself.
+    Data,
+    /// Anchor within the page that is not a heading of the note.
+    Anchor,
+    /// Id of the block a link to an input note or one of its headings
+    /// resolves to.
+    Block(String),
+    /// Local file that is not an input note, copied into the output directory.
+    Asset(PathBuf),
+    /// Relative link to a file that does not exist or a note that is not an
+    /// input.
+    Missing(PathBuf),
+}
+
+/// Whether `url` starts with a scheme like `https:`. Single letters are
+/// drive letters rather than schemes.
+pub fn has_scheme(url: &str) -> bool {
+    if url.starts_with("//") {
+        return true;
+    }
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.len() > 1
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    })
+}
+
+/// Path of a `file:` URL, on this machine.
+fn file_path(url: &str) -> Option<&str> {
+    let path = url.strip_prefix("file:")?;
+    let path = match path.strip_prefix("//") {
+        Some(path) => path.strip_prefix("localhost").unwrap_or(path),
+        None => path,
+    };
+    // Files on other machines can not be copied
+    path.starts_with('/').then_some(path)
+}
+
+fn decode(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().into_owned()
+}
+
+/// Classifies the destination of a link in the note `note`.
+pub fn classify(url: &str, vault: &Vault, note: usize) -> Destination {
+    if url.starts_with("data:") {
+        return Destination::Data;
+    }
+    // `file://` URLs are local files, with absolute paths
+    let (url, absolute) = match file_path(url) {
+        Some(path) => (path, true),
+        None if has_scheme(url) => return Destination::External,
+        None => (url, false),
+    };
+
+    let (path, fragment) = match url.split_once('#') {
+        Some((path, fragment)) => (path, Some(decode(fragment))),
+        None => (url, None),
+    };
+
+    if path.is_empty() {
+        let fragment = fragment.unwrap_or_default();
+        if vault.has_block(&fragment) {
+            return Destination::Anchor;
+        }
+        return match vault.resolve_in(note, Some(&fragment)) {
+            Some(block) => Destination::Block(block.id.clone()),
+            None => Destination::Anchor,
+        };
+    }
+
+    let path = decode(path);
+    let path = match path.strip_prefix('/') {
+        _ if absolute => PathBuf::from(path),
+        Some(path) => vault.root.join(path),
+        None => vault.notes[note]
+            .path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(path),
+    };
+
+    if let Some(target) = vault.find_note_by_path(&path) {
+        let block = vault
+            .resolve_in(target, fragment.as_deref())
+            .or_else(|| vault.resolve_in(target, None));
+        if let Some(block) = block {
+            return Destination::Block(block.id.clone());
+        }
+    }
+
+    if path.is_file() && path.extension().is_none_or(|extension| extension != "md") {
+        Destination::Asset(path)
+    } else {
+        vault::missed(path.clone());
+        Destination::Missing(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schemes() {
+        assert!(has_scheme("https://example.com"));
+        assert!(has_scheme("mailto:a@example.com"));
+        assert!(has_scheme("//example.com/x"));
+        assert!(has_scheme("git+ssh:x"));
+    }
+
+    #[test]
+    fn drive_letters_are_not_schemes() {
+        assert!(!has_scheme(r"C:\path\x.png"));
+        assert!(!has_scheme("c:/path/x.png"));
+        assert!(!has_scheme("c:"));
+    }
+
+    #[test]
+    fn relative_paths_have_no_scheme() {
+        assert!(!has_scheme("x.png"));
+        assert!(!has_scheme("a/b:c.png"));
+        assert!(!has_scheme("1a:x"));
+    }
+
+    #[test]
+    fn file_urls() {
+        assert_eq!(file_path("file:///x"), Some("/x"));
+        assert_eq!(file_path("file:/x"), Some("/x"));
+        assert_eq!(file_path("file://localhost/x"), Some("/x"));
+        assert_eq!(file_path("file://host/x"), None);
+        assert_eq!(file_path("https://host/x"), None);
+    }
+}
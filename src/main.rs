@@ -1,144 +1,730 @@
+mod assets;
+mod cache;
+mod check;
+mod code;
+mod config;
+mod diagnostics;
+mod error;
+mod extensions;
+mod highlight;
 mod html;
+mod input;
+mod links;
+mod math;
+mod metadata;
+mod parser;
+mod pdf;
+mod report;
+mod serve;
+mod split;
+mod standalone;
+mod vault;
+mod watch;
+mod wiki;
 
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 use itertools::Itertools;
-use pdf_writer::Finish;
-use pulldown_cmark::{self as md, Event, HeadingLevel, LinkType, Tag};
-use serde::Serialize;
+use pulldown_cmark::{self as md, CodeBlockKind, LinkType, Tag};
+use serde::{Deserialize, Serialize};
 use tera::{Context, Tera};
 
 use clap::Parser;
 
+use assets::Layout;
+use cache::{Cache, Rendered};
+use check::Checker;
+use config::Config;
+use diagnostics::Span;
+use error::Error;
+use extensions::{Extension, Extensions};
 use html::push_html;
+use links::Destination;
+use metadata::FrontMatter;
+use parser::Parser as MarkdownParser;
+use pdf::{PageSize, Sheet};
+use report::Report;
+use split::{Chunks, Preface, Split};
+use vault::{BlockIndex, Vault};
+use wiki::WikiLinks;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Build, then build again whenever an input, an asset or a template changes.
+    Watch(Args),
+    /// Report problems in the notes and templates, without writing anything.
+    Check(Args),
+    /// Watch, and serve the output on localhost, reloading open pages after every build.
+    Serve(ServeArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    #[command(flatten)]
+    args: Args,
+
+    /// Port to serve on.
+    #[arg(short, long, default_value_t = 8000)]
+    port: u16,
+}
+
+/// Flags overriding the configuration file.
+#[derive(clap::Args, Debug)]
 struct Args {
+    /// Configuration file, `cheatsheet.toml` if there is one.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Markdown files, directories to search for `*.md` files, or glob patterns.
     input: Vec<PathBuf>,
 
-    #[arg(short, long, default_value = "data/templates/")]
-    templates: PathBuf,
+    /// Only use files found in directories or by globs that match one of these patterns.
+    #[arg(long)]
+    include: Vec<glob::Pattern>,
+
+    /// Skip files found in directories or by globs that match one of these patterns.
+    #[arg(long)]
+    exclude: Vec<glob::Pattern>,
+
+    /// Folder with the templates and their stylesheets [default: data/templates/]
+    #[arg(short, long)]
+    templates: Option<PathBuf>,
+
+    /// [default: out]
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+
+    /// Root of the Obsidian vault, defaults to the common parent of the inputs.
+    #[arg(long)]
+    vault: Option<PathBuf>,
+
+    /// Folder `![[embeds]]` are looked up in, relative to the vault root.
+    #[arg(long)]
+    attachments: Option<PathBuf>,
+
+    /// Where to split notes into blocks, can be overridden per file with `split` in the front matter [default: h1]
+    #[arg(long, value_enum)]
+    split: Option<Split>,
+
+    /// What to do with content before the first split, can be overridden per file with `preface` in the front matter [default: block]
+    #[arg(long, value_enum)]
+    preface: Option<Preface>,
+
+    /// Outputs to write, can be given more than once [default: html]
+    #[arg(short, long, value_enum)]
+    format: Vec<Format>,
+
+    /// Markdown extensions to parse the notes with, replacing the configured ones.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    extensions: Vec<Extension>,
+
+    /// Markdown extensions to turn on, in addition to the configured ones.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    enable: Vec<Extension>,
+
+    /// Markdown extensions to turn off.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    disable: Vec<Extension>,
+
+    /// How linked files and images are laid out in the output [default: mirror]
+    #[arg(long, value_enum)]
+    assets: Option<Layout>,
+
+    /// Parser to read the notes with, can be overridden per file with `parser` in the front matter [default: pulldown-cmark]
+    #[arg(long, value_enum)]
+    parser: Option<MarkdownParser>,
+
+    /// Theme to highlight code with [default: InspiredGitHub]
+    #[arg(long)]
+    code_theme: Option<String>,
+
+    /// Write a single `index.html`, with the stylesheets inlined and the images and linked files embedded.
+    #[arg(long, overrides_with = "no_standalone")]
+    standalone: bool,
+
+    /// Write the stylesheets, images and linked files next to `index.html`, even if the configuration file sets `standalone`.
+    #[arg(long, overrides_with = "standalone")]
+    no_standalone: bool,
+
+    /// Paper size of the PDF [default: a4]
+    #[arg(long, value_enum)]
+    page_size: Option<PageSize>,
+
+    /// Use portrait instead of landscape pages in the PDF.
+    #[arg(long, overrides_with = "landscape")]
+    portrait: bool,
+
+    /// Use landscape pages in the PDF, even if the configuration file sets `portrait`.
+    #[arg(long, overrides_with = "portrait")]
+    landscape: bool,
+
+    /// Number of columns on every page of the PDF [default: 3]
+    #[arg(long)]
+    columns: Option<usize>,
+
+    /// Shrink the text of the PDF until it fits on this many pages.
+    #[arg(long)]
+    pages: Option<usize>,
+}
+
+impl Args {
+    /// Reads the configuration file, and overrides it with the flags that
+    /// were given.
+    fn config(self) -> Result<Config, Error> {
+        let mut config = match self.config {
+            Some(path) => Config::load(&path)?,
+            None if Path::new(config::FILE).is_file() => Config::load(Path::new(config::FILE))?,
+            None => Config::default(),
+        };
+        if !self.input.is_empty() {
+            config.inputs = self.input;
+        }
+        if !self.include.is_empty() {
+            config.include = self.include;
+        }
+        if !self.exclude.is_empty() {
+            config.exclude = self.exclude;
+        }
+        if !self.format.is_empty() {
+            config.formats = self.format;
+        }
+        if !self.extensions.is_empty() {
+            config.extensions = Extensions::Only(self.extensions);
+        }
+        for extension in self.enable {
+            config.extensions.toggle(extension, true);
+        }
+        for extension in self.disable {
+            config.extensions.toggle(extension, false);
+        }
+        config.templates = self.templates.unwrap_or(config.templates);
+        config.out = self.out.unwrap_or(config.out);
+        config.vault = self.vault.or(config.vault);
+        config.attachments = self.attachments.or(config.attachments);
+        config.assets = self.assets.unwrap_or(config.assets);
+        config.parser = self.parser.unwrap_or(config.parser);
+        config.split = self.split.unwrap_or(config.split);
+        config.preface = self.preface.unwrap_or(config.preface);
+        config.code_theme = self.code_theme.unwrap_or(config.code_theme);
+        config.standalone = flag(self.standalone, self.no_standalone).unwrap_or(config.standalone);
+        config.pdf.page_size = self.page_size.unwrap_or(config.pdf.page_size);
+        config.pdf.portrait = flag(self.portrait, self.landscape).unwrap_or(config.pdf.portrait);
+        config.pdf.columns = self.columns.unwrap_or(config.pdf.columns);
+        config.pdf.pages = self.pages.or(config.pdf.pages);
+        Ok(config)
+    }
+}
+
+/// Value of a pair of flags turning an option on and off, if either was
+/// given. The last one given wins.
+fn flag(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether a build writes the output, or only reports problems.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Write,
+    Check,
+}
 
-    #[arg(short, long, default_value = "out")]
-    out: PathBuf,
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    /// `index.html`, styled by the templates.
+    Html,
+    /// `cheatsheet.pdf`, laid out in the columns of the pages.
+    Pdf,
 }
 
 #[derive(Serialize, Debug)]
-struct Block {
+struct Block<'a> {
+    /// Anchor id, used as the target of links to this block.
+    id: String,
     content: String,
+    /// Classes and attributes of the heading starting the block, rendered on
+    /// the block instead of the heading.
+    classes: Vec<String>,
+    attrs: Vec<(String, String)>,
+    /// Whether the block holds the content before the first split of a note
+    /// that has other blocks as well.
+    preface: bool,
+    /// Front matter of the file the block is from.
+    meta: FrontMatter,
+    /// Options of the fenced code blocks in the block, read from their info
+    /// strings.
+    code: Vec<code::Info>,
+    /// Events the content was rendered from, for formats other than HTML.
+    #[serde(skip)]
+    events: Vec<md::Event<'a>>,
 }
 
-fn main() {
-    let args = Args::parse();
+#[derive(Serialize, Debug)]
+struct File {
+    name: String,
+    path: PathBuf,
+    meta: FrontMatter,
+}
 
-    dbg!(&args);
+/// Expands wiki links in the events of a block, points links at the blocks
+/// and files they refer to, and collects those files in `assets` and the
+/// URLs of remote images in `remote`. Problems are reported at the source
+/// ranges of the events.
+fn process<'a>(
+    events: Vec<(md::Event<'a>, Range<usize>)>,
+    block: &BlockIndex,
+    vault: &'a Vault,
+    note_id: usize,
+    layout: Layout,
+    assets: &mut Vec<PathBuf>,
+    remote: &mut Vec<String>,
+) -> Vec<md::Event<'a>> {
+    let note = &vault.notes[note_id];
 
-    let mut additional_files = vec![];
+    // Preprocessing
+    let mut title = block.title.is_some();
+    let parser = events.into_iter().map(|(event, range)| match event {
+        // Attributes of the title go on the block instead
+        pulldown_cmark::Event::Start(Tag::Heading { level, .. }) if std::mem::take(&mut title) => (
+            pulldown_cmark::Event::Start(Tag::Heading {
+                level,
+                id: None,
+                classes: vec![],
+                attrs: vec![],
+            }),
+            range,
+        ),
+        _ => (event, range),
+    });
+    let parser = WikiLinks::new(parser, vault, note_id);
 
-    let blocks = args
-        .input
-        .into_iter()
-        .flat_map(|filename| {
-            dbg!(&filename);
-            let src = std::fs::read_to_string(&filename).unwrap();
-
-            let mut options = md::Options::empty();
-            // options.insert(md::Options::ENABLE_MATH);
-            options.insert(md::Options::ENABLE_TABLES);
-            options.insert(md::Options::ENABLE_GFM);
-            options.insert(md::Options::ENABLE_FOOTNOTES);
-            options.insert(md::Options::ENABLE_STRIKETHROUGH);
-            options.insert(md::Options::ENABLE_SMART_PUNCTUATION);
-            options.insert(md::Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS);
-            options.insert(md::Options::ENABLE_DEFINITION_LIST);
-
-            let parser = md::Parser::new_ext(&src, options);
-
-            // Preprocessing
-            let parser = parser.map(|event| match event {
-                // pulldown_cmark::Event::Start(Tag::BlockQuote(Some(kind))) => md::Event::Html(),
-                _ => event,
-            });
-
-            // Pull in aditional files
-            let parser = parser.map(|event| {
-                dbg!(&event);
-                match &event {
-                    pulldown_cmark::Event::Start(Tag::Link { .. }) => todo!(),
-                    pulldown_cmark::Event::Start(Tag::Image {
-                        link_type: LinkType::Inline,
-                        dest_url,
-                        ..
-                    }) => additional_files.push(
-                        filename
-                            .parent()
-                            .unwrap()
-                            .join(dest_url.as_ref())
-                            .to_path_buf(),
-                    ),
-                    _ => {}
+    // Pull in aditional files
+    let parser = parser.map(|(event, range)| {
+        match event {
+            pulldown_cmark::Event::Start(Tag::Link {
+                link_type: LinkType::Email,
+                ..
+            }) => event,
+            pulldown_cmark::Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                let dest_url = match links::classify(&dest_url, vault, note_id) {
+                    Destination::External | Destination::Data | Destination::Anchor => dest_url,
+                    Destination::Block(id) => format!("#{}", id).into(),
+                    Destination::Asset(path) => {
+                        let dest_url =
+                            assets::url(&assets::output_path(&path, &vault.root, layout));
+                        assets.push(path);
+                        dest_url.into()
+                    }
+                    Destination::Missing(path) => {
+                        diagnostics::warning_at(
+                            &Span::new(&note.path, &note.src, range),
+                            format_args!("link to missing file `{}`", path.display()),
+                        );
+                        dest_url
+                    }
                 };
-                event
-            });
-
-            // Post Processing
-            let parser = parser.map(|event| match event {
-                _ => event,
-            });
-
-            // Split by H1 headings
-            let mut chunk_id = 0;
-            let parsers = parser.chunk_by(|event| match event {
-                pulldown_cmark::Event::Start(Tag::Heading {
-                    level: HeadingLevel::H1,
-                    ..
-                }) => {
-                    chunk_id += 1;
-                    chunk_id
+                pulldown_cmark::Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                })
+            }
+            // Reference images have their destination resolved already
+            pulldown_cmark::Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                let dest_url = match links::classify(&dest_url, vault, note_id) {
+                    Destination::Asset(path) => {
+                        let dest_url =
+                            assets::url(&assets::output_path(&path, &vault.root, layout));
+                        assets.push(path);
+                        dest_url.into()
+                    }
+                    Destination::Data => dest_url,
+                    Destination::External => {
+                        remote.push(dest_url.to_string());
+                        dest_url
+                    }
+                    Destination::Missing(_) | Destination::Anchor | Destination::Block(_) => {
+                        diagnostics::warning_at(
+                            &Span::new(&note.path, &note.src, range),
+                            format_args!("image `{}` not found", dest_url),
+                        );
+                        dest_url
+                    }
+                };
+                pulldown_cmark::Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                })
+            }
+            pulldown_cmark::Event::InlineMath(ref tex)
+            | pulldown_cmark::Event::DisplayMath(ref tex) => {
+                let display = matches!(event, pulldown_cmark::Event::DisplayMath(_));
+                if let Err(err) = math::to_mathml(tex, display) {
+                    diagnostics::warning_at(
+                        &Span::new(&note.path, &note.src, range),
+                        format_args!("failed to render math: {}", err),
+                    );
                 }
-                _ => chunk_id,
-            });
+                event
+            }
+            _ => event,
+        }
+    });
+
+    parser.collect()
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    if let Err(err) = run(cli) {
+        diagnostics::report(&err);
+        std::process::exit(err.exit_code());
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Error> {
+    match cli.command {
+        None => {
+            let config = cli.args.config()?;
+            let mut cache = Cache::load(&config.out);
+            build(&config, &mut cache, Mode::Write)?.print();
+            match diagnostics::errors() {
+                0 => Ok(()),
+                errors => Err(Error::Reported(errors)),
+            }
+        }
+        Some(Command::Watch(args)) => watch::watch(&args.config()?, || {}),
+        Some(Command::Check(args)) => {
+            // Without a cache every block is rendered, so nothing is missed
+            build(&args.config()?, &mut Cache::default(), Mode::Check)?;
+            match diagnostics::errors() + diagnostics::warnings() {
+                0 => Ok(()),
+                problems => Err(Error::Problems(problems)),
+            }
+        }
+        Some(Command::Serve(serve)) => serve::serve(&serve.args.config()?, serve.port),
+    }
+}
+
+/// Builds the cheatsheet, and reports the files it was built from and the
+/// ones it wrote.
+fn build(config: &Config, cache: &mut Cache, mode: Mode) -> Result<Report, Error> {
+    diagnostics::reset();
+    let options = config
+        .extensions
+        .options(extensions::options(extensions::DEFAULT));
+    let html = config.formats.contains(&Format::Html);
+    let pdf = config.formats.contains(&Format::Pdf);
+    let theme = highlight::Theme::new(&config.code_theme)
+        .ok_or_else(|| Error::Theme(config.code_theme.clone()))?;
+
+    let inputs = input::expand(&config.inputs, &config.include, &config.exclude);
+    let vault = Vault::new(
+        &inputs,
+        config.vault.clone(),
+        config.attachments.clone(),
+        options,
+        config.parser,
+        config.split,
+        config.preface,
+    );
+    if vault.notes.is_empty() {
+        return Err(Error::NoInput);
+    }
+
+    let mut additional_files = vec![];
+    let mut remote = vec![];
+    let vault_key = cache::vault_key(&vault, config.assets);
+    let mut checker = (mode == Mode::Check).then(Checker::default);
+
+    let blocks = vault
+        .notes
+        .iter()
+        .enumerate()
+        .flat_map(|(note_id, note)| {
+            let filename = &note.path;
+
+            if let Some(checker) = &mut checker {
+                checker.note(note);
+            }
+
+            let parser = note.events().into_iter();
+
+            // Split into blocks
+            let parsers =
+                Chunks::new(parser, note.split, note.preface).chunk_by(|(chunk, _, _)| *chunk);
 
             // Render HTML and generate blocks
-            let blocks = parsers
+            parsers
                 .into_iter()
+                .filter(|(i, _)| !note.blocks[*i].empty)
                 .map(|(i, parser)| {
-                    let mut html = String::new();
-                    push_html(&mut html, parser);
-                    // pulldown_cmark::html::push_html(&mut html, parser);
-                    Block { content: html }
-                })
-                .collect::<Vec<_>>();
+                    let block = &note.blocks[i];
+                    let events = parser
+                        .map(|(_, event, range)| (event, range))
+                        .collect::<Vec<_>>();
+                    if let Some(checker) = &mut checker {
+                        checker.block(note, block, &events);
+                    }
+                    let code = events
+                        .iter()
+                        .filter_map(|(event, _)| match event {
+                            md::Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                                Some(code::Info::parse(info))
+                            }
+                            _ => None,
+                        })
+                        .collect();
 
-            blocks
+                    let (content, events) = if pdf {
+                        // The PDF needs the events, so the cache is of no use
+                        let mut urls = vec![];
+                        let events = process(
+                            events,
+                            block,
+                            &vault,
+                            note_id,
+                            config.assets,
+                            &mut additional_files,
+                            &mut urls,
+                        );
+                        remote.extend(urls.into_iter().map(|url| (url, note.path.clone())));
+                        let mut content = String::new();
+                        if html {
+                            push_html(&mut content, events.iter().cloned());
+                        }
+                        (content, events)
+                    } else {
+                        // The block only needs to be rendered again if
+                        // its source, anything it transcludes or the
+                        // links it may point to changed, or it moved to
+                        // other lines, which messages about it name
+                        let plain = events
+                            .iter()
+                            .map(|(event, _)| event.clone())
+                            .collect::<Vec<_>>();
+                        let transcluded = wiki::transcluded(&vault, note_id, &plain)
+                            .into_iter()
+                            .map(|note| &vault.notes[note].src)
+                            .collect::<Vec<_>>();
+                        let start = events.iter().map(|(_, range)| range.start).min();
+                        let line = note.src[..start.unwrap_or(0)].matches('\n').count();
+                        let key = cache::key((
+                            vault_key,
+                            filename,
+                            &block.id,
+                            format!("{:?}", plain),
+                            line,
+                            transcluded,
+                        ));
+                        let rendered = cache.render(key, || {
+                            let mut assets = vec![];
+                            let mut remote = vec![];
+                            let events = process(
+                                events,
+                                block,
+                                &vault,
+                                note_id,
+                                config.assets,
+                                &mut assets,
+                                &mut remote,
+                            );
+                            let mut html = String::new();
+                            push_html(&mut html, events.into_iter());
+                            // Hashed names change with the contents
+                            let stamps = match config.assets {
+                                Layout::Hash => cache::stamps(&assets),
+                                Layout::Mirror => vec![],
+                            };
+                            Rendered {
+                                html,
+                                assets,
+                                remote,
+                                stamps,
+                                ..Default::default()
+                            }
+                        });
+                        additional_files.extend(rendered.assets);
+                        remote.extend(
+                            rendered
+                                .remote
+                                .into_iter()
+                                .map(|url| (url, note.path.clone())),
+                        );
+                        (rendered.html, vec![])
+                    };
+
+                    Block {
+                        id: block.id.clone(),
+                        content,
+                        classes: block.classes.clone(),
+                        attrs: block.attrs.clone(),
+                        preface: i == 0 && note.blocks.len() > 1,
+                        meta: note.meta.clone(),
+                        code,
+                        events,
+                    }
+                })
+                .collect::<Vec<_>>()
         })
         .collect::<Vec<_>>();
 
-    dbg!(&additional_files);
-    fs_extra::copy_items(
-        &additional_files,
-        args.out.to_str().unwrap(),
-        &fs_extra::dir::CopyOptions::default().overwrite(true),
-    )
-    .unwrap();
-
-    fs_extra::copy_items(
-        &[&args.templates.join("css")],
-        args.out.to_str().unwrap(),
-        &fs_extra::dir::CopyOptions::default().overwrite(true),
-    )
-    .unwrap();
-
-    let mut tera = Tera::new(args.templates.join("*.html").to_str().unwrap()).unwrap();
+    let assets = additional_files.into_iter().unique().collect::<Vec<_>>();
+    let copies = assets::plan(&assets, &vault.root, config.assets);
+
+    let mut report = Report {
+        sources: vault
+            .notes
+            .iter()
+            .map(|note| note.path.clone())
+            .chain(assets.iter().cloned())
+            .collect(),
+        remote: remote.into_iter().unique().collect(),
+        ..Default::default()
+    };
+
+    if mode == Mode::Write {
+        std::fs::create_dir_all(&config.out).map_err(|source| Error::Write {
+            path: config.out.clone(),
+            source,
+        })?;
+        // Blocks are only cached when the HTML is rendered without a PDF,
+        // other builds leave the cache of the last one as it is
+        if html && !pdf {
+            cache.save(&config.out)?;
+        }
+
+        // Standalone pages have the assets in them, only the PDF needs
+        // copies
+        if !config.standalone || pdf {
+            for (path, relative) in &copies {
+                copy_if_changed(path, &config.out.join(relative))?;
+            }
+            report.copied = copies.clone();
+        }
+    }
+
+    // The PDF is laid out with the copied images
+    if pdf && mode == Mode::Write {
+        let sheet = Sheet {
+            size: config.pdf.page_size,
+            portrait: config.pdf.portrait,
+            columns: config.pdf.columns,
+            pages: config.pdf.pages,
+        };
+        let pdf = pdf::render(&blocks, &config.out, &sheet, &theme);
+        let path = config.out.join("cheatsheet.pdf");
+        write(&path, pdf)?;
+        report.written.push(path);
+    }
+    if !html {
+        return Ok(report);
+    }
+
+    if mode == Mode::Write && !config.standalone {
+        fs_extra::copy_items(
+            &[&config.templates.join("css")],
+            &config.out,
+            &fs_extra::dir::CopyOptions::default().overwrite(true),
+        )?;
+        write(&config.out.join(highlight::STYLESHEET), theme.stylesheet())?;
+    }
+
+    let glob = config.templates.join("*.html");
+    let mut tera = Tera::new(
+        glob.to_str()
+            .ok_or_else(|| Error::Templates(config.templates.clone()))?,
+    )?;
     tera.autoescape_on(vec![]);
 
     let mut context = Context::new();
     context.insert("blocks", &blocks);
+    context.insert("vars", &config.vars);
+    context.insert(
+        "files",
+        &vault
+            .notes
+            .iter()
+            .map(|note| File {
+                name: note.name.clone(),
+                path: note.path.clone(),
+                meta: note.meta.clone(),
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    let mut result = tera.render("index.html", &context)?;
+    if config.standalone {
+        let stylesheets = [(highlight::STYLESHEET, theme.stylesheet())];
+        let (page, embedded) =
+            standalone::inline(&result, &config.templates, &copies, &stylesheets);
+        result = page;
+        report.embedded = embedded.into_iter().unique().collect();
+    }
 
-    let result = tera.render("index.html", &context).unwrap();
+    if mode == Mode::Write {
+        let path = config.out.join("index.html");
+        write(&path, result)?;
+        report.written.push(path);
+    }
+    Ok(report)
+}
 
-    dbg!(&result);
+fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), Error> {
+    std::fs::write(path, contents).map_err(|source| Error::Write {
+        path: path.to_path_buf(),
+        source,
+    })
+}
 
-    std::fs::write(args.out.join("index.html"), result).unwrap();
+/// Copies `from` to `to`, unless `to` is a copy of the current version of
+/// `from` already.
+fn copy_if_changed(from: &Path, to: &Path) -> Result<(), Error> {
+    let source = from.metadata().map_err(|source| Error::Read {
+        path: from.to_path_buf(),
+        source,
+    })?;
+    let unchanged = to.metadata().is_ok_and(|copy| {
+        source.len() == copy.len() && source.modified().ok() <= copy.modified().ok()
+    });
+    if !unchanged {
+        let write = |source| Error::Write {
+            path: to.to_path_buf(),
+            source,
+        };
+        if let Some(dir) = to.parent() {
+            std::fs::create_dir_all(dir).map_err(write)?;
+        }
+        std::fs::copy(from, to).map_err(write)?;
+    }
+    Ok(())
 }
@@ -0,0 +1,16 @@
+//! Server-side rendering of TeX math to MathML, so pages need no JavaScript.
+
+/// Renders `tex` to MathML, as a block if `display` is set.
+///
+/// Rendering runs KaTeX in a JavaScript engine, which is slow, so results are
+/// memoized.
+#[comemo::memoize]
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, String> {
+    let opts = katex::Opts::builder()
+        .output_type(katex::OutputType::Mathml)
+        .display_mode(display)
+        .throw_on_error(true)
+        .build()
+        .unwrap();
+    katex::render_with_opts(tex, &opts).map_err(|err| err.to_string())
+}
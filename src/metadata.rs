@@ -0,0 +1,113 @@
+//! Front matter of notes, in TOML (`+++`) or YAML (`---`) metadata blocks.
+
+use std::ops::Range;
+
+use pulldown_cmark::{self as md, Event, MetadataBlockKind, Tag, TagEnd};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::extensions::Extensions;
+use crate::parser::Parser;
+use crate::split::{Preface, Split};
+
+/// Fields of the front matter, available to templates as `block.meta` and
+/// `notes[i].meta`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    /// Position of the note in the cheatsheet, notes without it come last.
+    pub order: Option<i64>,
+    #[serde(deserialize_with = "string_or_list")]
+    pub tags: Vec<String>,
+    /// Accent color of the blocks of the note.
+    pub color: Option<String>,
+    /// Number of text columns in the blocks of the note.
+    pub columns: Option<u32>,
+    /// Where to split the note into blocks, overriding `--split`.
+    pub split: Option<Split>,
+    /// What to do with content before the first split, overriding `--preface`.
+    pub preface: Option<Preface>,
+    /// Markdown extensions of the note, or the ones to turn on or off for it.
+    pub extensions: Option<Extensions>,
+    /// Parser the note is read with, overriding `--parser`.
+    pub parser: Option<Parser>,
+    /// Any other fields, passed on to templates as is.
+    #[serde(flatten)]
+    pub extra: tera::Map<String, tera::Value>,
+}
+
+/// Obsidian accepts `tags: a` as well as `tags: [a, b]`.
+fn string_or_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>),
+    }
+    Ok(match StringOrList::deserialize(deserializer)? {
+        StringOrList::String(tags) => tags
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+        StringOrList::List(tags) => tags,
+    })
+}
+
+/// Front matter that could not be read.
+#[derive(Debug)]
+pub struct Error {
+    pub message: String,
+    /// Where in the note the problem is.
+    pub range: Range<usize>,
+}
+
+/// Reads the front matter at the start of `src`.
+pub fn parse(src: &str, options: md::Options) -> Result<FrontMatter, Error> {
+    let mut parser = md::Parser::new_ext(src, options).into_offset_iter();
+    // Later metadata blocks are rules and text, see `parser::parse`
+    let Some((Event::Start(Tag::MetadataBlock(kind)), block)) = parser.next() else {
+        return Ok(FrontMatter::default());
+    };
+    if block.start != 0 {
+        return Ok(FrontMatter::default());
+    }
+    let mut start = None;
+    let text = parser
+        .take_while(|(event, _)| !matches!(event, Event::End(TagEnd::MetadataBlock(_))))
+        .filter_map(|(event, range)| match event {
+            Event::Text(text) => {
+                start.get_or_insert(range.start);
+                Some(text.into_string())
+            }
+            _ => None,
+        })
+        .collect::<String>();
+    let start = start.unwrap_or(block.start);
+    let error = |message: String, range: Option<Range<usize>>| Error {
+        message,
+        range: range.map_or(block.clone(), |range| {
+            start + range.start..start + range.end
+        }),
+    };
+
+    match kind {
+        MetadataBlockKind::PlusesStyle => {
+            toml::from_str(&text).map_err(|err| error(err.message().to_string(), err.span()))
+        }
+        MetadataBlockKind::YamlStyle if text.trim().is_empty() => Ok(FrontMatter::default()),
+        MetadataBlockKind::YamlStyle => serde_yaml::from_str(&text).map_err(|err| {
+            let index = err.location().map(|location| location.index());
+            // The location is shown with the line instead
+            let message = err.to_string();
+            let message = match message.rfind(" at line ") {
+                Some(end) => message[..end].to_string(),
+                None => message,
+            };
+            error(message, index.map(|index| index..index + 1))
+        }),
+    }
+}
@@ -0,0 +1,456 @@
+//! Markdown parsers notes can be read with. All of them are turned into the
+//! events of pulldown-cmark, which the rest of the pipeline works on.
+
+mod comrak;
+mod markdown_rs;
+
+use std::ops::Range;
+
+use pulldown_cmark::{self as md, BlockQuoteKind, CodeBlockKind, CowStr, Event, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Parser {
+    #[default]
+    PulldownCmark,
+    /// Also reads bare autolinks, `^superscript^` and description lists.
+    Comrak,
+    /// Reads MDX instead of HTML, `<Jsx elements>` and `{expressions}`.
+    MarkdownRs,
+}
+
+/// Note that could not be parsed.
+#[derive(Debug)]
+pub struct Error {
+    pub message: String,
+    /// Where in the note the problem is.
+    pub range: Range<usize>,
+}
+
+/// Parses `src` into events with their source ranges. Extensions a parser
+/// does not know are left out.
+///
+/// Notes are read several times per build, to index, check and render them
+/// and for every transclusion, so the events are memoized.
+#[comemo::memoize]
+pub fn parse(
+    src: &str,
+    parser: Parser,
+    options: md::Options,
+) -> Vec<(Event<'static>, Range<usize>)> {
+    let events = match parser {
+        Parser::PulldownCmark => pulldown_cmark(src, options),
+        Parser::Comrak => alerts(
+            heading_attributes(comrak::parse(src, options), options),
+            options,
+        ),
+        // Notes are checked before, those markdown-rs can not read are read
+        // by pulldown-cmark
+        Parser::MarkdownRs => match markdown_rs::parse(src, options) {
+            Ok(events) => alerts(heading_attributes(events, options), options),
+            Err(_) => pulldown_cmark(src, options),
+        },
+    };
+    events
+        .into_iter()
+        .map(|(event, range)| (owned_event(event), range))
+        .collect()
+}
+
+/// Copies the text an event borrows from the source.
+fn owned_event(event: Event<'_>) -> Event<'static> {
+    match event {
+        Event::Start(tag) => Event::Start(owned_tag(tag)),
+        Event::End(tag) => Event::End(tag),
+        Event::Text(text) => Event::Text(owned(text)),
+        Event::Code(text) => Event::Code(owned(text)),
+        Event::InlineMath(text) => Event::InlineMath(owned(text)),
+        Event::DisplayMath(text) => Event::DisplayMath(owned(text)),
+        Event::Html(html) => Event::Html(owned(html)),
+        Event::InlineHtml(html) => Event::InlineHtml(owned(html)),
+        Event::FootnoteReference(label) => Event::FootnoteReference(owned(label)),
+        Event::SoftBreak => Event::SoftBreak,
+        Event::HardBreak => Event::HardBreak,
+        Event::Rule => Event::Rule,
+        Event::TaskListMarker(checked) => Event::TaskListMarker(checked),
+    }
+}
+
+fn owned_tag(tag: Tag<'_>) -> Tag<'static> {
+    match tag {
+        Tag::Paragraph => Tag::Paragraph,
+        Tag::Heading {
+            level,
+            id,
+            classes,
+            attrs,
+        } => Tag::Heading {
+            level,
+            id: id.map(owned),
+            classes: classes.into_iter().map(owned).collect(),
+            attrs: attrs
+                .into_iter()
+                .map(|(key, value)| (owned(key), value.map(owned)))
+                .collect(),
+        },
+        Tag::BlockQuote(kind) => Tag::BlockQuote(kind),
+        Tag::CodeBlock(CodeBlockKind::Indented) => Tag::CodeBlock(CodeBlockKind::Indented),
+        Tag::CodeBlock(CodeBlockKind::Fenced(info)) => {
+            Tag::CodeBlock(CodeBlockKind::Fenced(owned(info)))
+        }
+        Tag::HtmlBlock => Tag::HtmlBlock,
+        Tag::List(start) => Tag::List(start),
+        Tag::Item => Tag::Item,
+        Tag::FootnoteDefinition(label) => Tag::FootnoteDefinition(owned(label)),
+        Tag::DefinitionList => Tag::DefinitionList,
+        Tag::DefinitionListTitle => Tag::DefinitionListTitle,
+        Tag::DefinitionListDefinition => Tag::DefinitionListDefinition,
+        Tag::Table(alignments) => Tag::Table(alignments),
+        Tag::TableHead => Tag::TableHead,
+        Tag::TableRow => Tag::TableRow,
+        Tag::TableCell => Tag::TableCell,
+        Tag::Emphasis => Tag::Emphasis,
+        Tag::Strong => Tag::Strong,
+        Tag::Strikethrough => Tag::Strikethrough,
+        Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        } => Tag::Link {
+            link_type,
+            dest_url: owned(dest_url),
+            title: owned(title),
+            id: owned(id),
+        },
+        Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        } => Tag::Image {
+            link_type,
+            dest_url: owned(dest_url),
+            title: owned(title),
+            id: owned(id),
+        },
+        Tag::MetadataBlock(kind) => Tag::MetadataBlock(kind),
+    }
+}
+
+fn owned(text: CowStr<'_>) -> CowStr<'static> {
+    match text {
+        CowStr::Inlined(text) => CowStr::Inlined(text),
+        text => text.into_string().into(),
+    }
+}
+
+/// Options that make pulldown-cmark read metadata blocks.
+const METADATA: md::Options = md::Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+    .union(md::Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS);
+
+/// pulldown-cmark reads metadata blocks anywhere in a note, only the one at
+/// its start is front matter. The rest of the note is read without them, so
+/// later `---` lines are rules.
+fn pulldown_cmark(src: &str, options: md::Options) -> Vec<(Event<'_>, Range<usize>)> {
+    let mut events = vec![];
+    let mut rest = 0;
+    let mut parser = md::Parser::new_ext(src, options).into_offset_iter();
+    if let Some((event @ Event::Start(Tag::MetadataBlock(_)), range)) = parser.next() {
+        if range.start == 0 {
+            rest = range.end;
+            events.push((event, range));
+            for (event, range) in parser {
+                let end = matches!(event, Event::End(TagEnd::MetadataBlock(_)));
+                events.push((event, range));
+                if end {
+                    break;
+                }
+            }
+        }
+    }
+    events.extend(
+        md::Parser::new_ext(&src[rest..], options - METADATA)
+            .into_offset_iter()
+            .map(|(event, range)| (event, rest + range.start..rest + range.end)),
+    );
+    events
+}
+
+/// Checks that `parser` can read `src`, which only fails for MDX syntax
+/// errors.
+pub fn check(src: &str, parser: Parser, options: md::Options) -> Result<(), Error> {
+    match parser {
+        Parser::MarkdownRs => markdown_rs::parse(src, options).map(|_| ()),
+        Parser::PulldownCmark | Parser::Comrak => Ok(()),
+    }
+}
+
+/// Start offsets of the lines of a source, to turn lines and columns into
+/// byte ranges.
+struct Lines<'a> {
+    src: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(src: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { src, starts }
+    }
+
+    /// Byte offset of the 1-based `line` and byte `column`, moved back to a
+    /// character boundary.
+    fn offset(&self, line: usize, column: usize) -> usize {
+        let Some(start) = line.checked_sub(1).and_then(|line| self.starts.get(line)) else {
+            return self.src.len();
+        };
+        self.boundary(start + column.saturating_sub(1))
+    }
+
+    fn boundary(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.src.len());
+        while !self.src.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+}
+
+/// Turns block quotes starting with `[!NOTE]` into GitHub alerts, the way
+/// pulldown-cmark reads them with GFM.
+fn alerts(
+    events: Vec<(Event<'_>, Range<usize>)>,
+    options: md::Options,
+) -> Vec<(Event<'_>, Range<usize>)> {
+    if !options.contains(md::Options::ENABLE_GFM) {
+        return events;
+    }
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter().peekable();
+    while let Some((event, range)) = events.next() {
+        let Event::Start(Tag::BlockQuote(None)) = event else {
+            out.push((event, range));
+            continue;
+        };
+        let quote = out.len();
+        out.push((Event::Start(Tag::BlockQuote(None)), range));
+        let Some((Event::Start(Tag::Paragraph), paragraph)) =
+            events.next_if(|(event, _)| matches!(event, Event::Start(Tag::Paragraph)))
+        else {
+            continue;
+        };
+        let kind = match events.peek() {
+            Some((Event::Text(text), _)) => alert_kind(text),
+            _ => None,
+        };
+        let Some(kind) = kind else {
+            out.push((Event::Start(Tag::Paragraph), paragraph));
+            continue;
+        };
+        out[quote].0 = Event::Start(Tag::BlockQuote(Some(kind)));
+        events.next();
+        events.next_if(|(event, _)| matches!(event, Event::SoftBreak));
+        // The marker may have been all of the paragraph
+        if events
+            .next_if(|(event, _)| matches!(event, Event::End(TagEnd::Paragraph)))
+            .is_none()
+        {
+            out.push((Event::Start(Tag::Paragraph), paragraph));
+        }
+    }
+    out
+}
+
+/// Reads the `{#id .class key=value}` at the end of headings into their
+/// attributes, the way pulldown-cmark does, for parsers that leave them in
+/// the text.
+fn heading_attributes(
+    mut events: Vec<(Event<'_>, Range<usize>)>,
+    options: md::Options,
+) -> Vec<(Event<'_>, Range<usize>)> {
+    if !options.contains(md::Options::ENABLE_HEADING_ATTRIBUTES) {
+        return events;
+    }
+    let mut heading = None;
+    for i in 0..events.len() {
+        match &events[i].0 {
+            Event::Start(Tag::Heading { .. }) => heading = Some(i),
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(start) = heading.take() {
+                    if let Some(text) = i.checked_sub(1).filter(|&text| text > start) {
+                        read_attributes(&mut events, start, text);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // Headings that were only attributes are left with an empty text
+    events.retain(|(event, _)| !matches!(event, Event::Text(text) if text.is_empty()));
+    events
+}
+
+/// Moves the attributes at the end of the text event `text` onto the heading
+/// started by the event `start`.
+fn read_attributes(events: &mut [(Event<'_>, Range<usize>)], start: usize, text: usize) {
+    let (Event::Text(content), range) = &events[text] else {
+        return;
+    };
+    let trimmed = content.trim_end();
+    let Some(open) = trimmed.strip_suffix('}').and_then(|rest| rest.rfind('{')) else {
+        return;
+    };
+    let inner = &trimmed[open + 1..trimmed.len() - 1];
+    if inner.contains(['{', '}']) {
+        return;
+    }
+    let before = content[..open].trim_end();
+    // Ranges only shrink with the text if they are its source
+    let range = if range.len() == content.len() {
+        range.start..range.start + before.len()
+    } else {
+        range.clone()
+    };
+    let (mut id, mut classes, mut attrs) = (None, vec![], vec![]);
+    for word in inner.split_whitespace() {
+        if let Some(value) = word.strip_prefix('#') {
+            id = Some(CowStr::from(value.to_string()));
+        } else if let Some(class) = word.strip_prefix('.') {
+            classes.push(CowStr::from(class.to_string()));
+        } else {
+            let (key, value) = match word.split_once('=') {
+                Some((key, value)) => (key, Some(CowStr::from(value.to_string()))),
+                None => (word, None),
+            };
+            attrs.push((CowStr::from(key.to_string()), value));
+        }
+    }
+    let only_attributes = before.is_empty();
+    events[text] = (Event::Text(before.to_string().into()), range);
+    // The attributes may be a text of their own, after the rest of the title
+    if only_attributes && text > start + 1 {
+        if let (Event::Text(content), range) = &mut events[text - 1] {
+            let trimmed = content.trim_end();
+            if range.len() == content.len() {
+                range.end = range.start + trimmed.len();
+            }
+            *content = trimmed.to_string().into();
+        }
+    }
+    if let Event::Start(Tag::Heading {
+        id: heading_id,
+        classes: heading_classes,
+        attrs: heading_attrs,
+        ..
+    }) = &mut events[start].0
+    {
+        *heading_id = id;
+        *heading_classes = classes;
+        *heading_attrs = attrs;
+    }
+}
+
+fn alert_kind(text: &str) -> Option<BlockQuoteKind> {
+    let kind = text.trim_end().strip_prefix("[!")?.strip_suffix(']')?;
+    match kind.to_ascii_uppercase().as_str() {
+        "NOTE" => Some(BlockQuoteKind::Note),
+        "TIP" => Some(BlockQuoteKind::Tip),
+        "IMPORTANT" => Some(BlockQuoteKind::Important),
+        "WARNING" => Some(BlockQuoteKind::Warning),
+        "CAUTION" => Some(BlockQuoteKind::Caution),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata_blocks(src: &str) -> Vec<Range<usize>> {
+        let options = md::Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+        parse(src, Parser::PulldownCmark, options)
+            .into_iter()
+            .filter(|(event, _)| matches!(event, Event::Start(Tag::MetadataBlock(_))))
+            .map(|(_, range)| range)
+            .collect()
+    }
+
+    /// Attributes of the headings, and the text of the note.
+    fn headings(src: &str, parser: Parser) -> (Vec<String>, String) {
+        let events = parse(src, parser, md::Options::ENABLE_HEADING_ATTRIBUTES);
+        let attributes = events
+            .iter()
+            .filter_map(|(event, _)| match event {
+                Event::Start(Tag::Heading {
+                    id, classes, attrs, ..
+                }) => {
+                    let attrs = attrs
+                        .iter()
+                        .map(|(key, value)| match value {
+                            Some(value) => format!("{}={}", key, value),
+                            None => key.to_string(),
+                        })
+                        .collect::<Vec<_>>();
+                    Some(format!(
+                        "#{} .{} {}",
+                        id.as_deref().unwrap_or(""),
+                        classes.join(" ."),
+                        attrs.join(" ")
+                    ))
+                }
+                _ => None,
+            })
+            .collect();
+        let text = events
+            .iter()
+            .filter_map(|(event, _)| match event {
+                Event::Text(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("|");
+        (attributes, text)
+    }
+
+    #[test]
+    fn heading_attributes_with_every_parser() {
+        let src = "# Git {.wide #git span=2 open}\n\n## Plain\n\n### {.only}\n";
+        for parser in [Parser::PulldownCmark, Parser::Comrak, Parser::MarkdownRs] {
+            let (attributes, text) = headings(src, parser);
+            assert_eq!(
+                attributes,
+                vec!["#git .wide span=2 open", "# . ", "# .only "]
+            );
+            assert_eq!(text, "Git|Plain", "{:?}", parser);
+        }
+    }
+
+    #[test]
+    fn front_matter_only_at_start() {
+        assert_eq!(metadata_blocks("---\na: 1\n---\n# A\n"), vec![0..12]);
+        assert!(metadata_blocks("# A\n\ntext\n\n---\n# B\nmore\n---\n").is_empty());
+    }
+
+    #[test]
+    fn later_metadata_blocks_are_rules() {
+        let src = "---\na: 1\n---\n# A\n\n---\n# B\nmore\n\n---\n";
+        let events = parse(src, Parser::PulldownCmark, md::Options::all());
+        let rules = events
+            .iter()
+            .filter(|(event, _)| matches!(event, Event::Rule))
+            .count();
+        assert_eq!(metadata_blocks(src).len(), 1);
+        assert_eq!(rules, 2);
+        let heading = events
+            .iter()
+            .find(|(event, _)| matches!(event, Event::Text(text) if &**text == "B"))
+            .map(|(_, range)| &src[range.clone()]);
+        assert_eq!(heading, Some("B"));
+    }
+}
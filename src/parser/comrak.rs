@@ -0,0 +1,263 @@
+//! Reading notes with comrak.
+
+use std::ops::Range;
+
+use comrak::nodes::{AstNode, ListType, NodeValue, Sourcepos, TableAlignment};
+use comrak::{Arena, Options};
+use pulldown_cmark::{
+    self as md, Alignment, CodeBlockKind, CowStr, Event, HeadingLevel, LinkType, MetadataBlockKind,
+    Tag, TagEnd,
+};
+
+use super::Lines;
+
+pub fn parse(src: &str, options: md::Options) -> Vec<(Event<'static>, Range<usize>)> {
+    let mut comrak = Options::default();
+    let extension = &mut comrak.extension;
+    extension.table = options.contains(md::Options::ENABLE_TABLES);
+    extension.strikethrough = options.contains(md::Options::ENABLE_STRIKETHROUGH);
+    extension.tasklist = options.contains(md::Options::ENABLE_TASKLISTS);
+    extension.footnotes = options.contains(md::Options::ENABLE_FOOTNOTES);
+    extension.description_lists = options.contains(md::Options::ENABLE_DEFINITION_LIST);
+    extension.math_dollars = options.contains(md::Options::ENABLE_MATH);
+    // Only one kind of front matter is read
+    extension.front_matter_delimiter =
+        if options.contains(md::Options::ENABLE_YAML_STYLE_METADATA_BLOCKS) {
+            Some("---".to_string())
+        } else if options.contains(md::Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS) {
+            Some("+++".to_string())
+        } else {
+            None
+        };
+    extension.autolink = true;
+    extension.superscript = true;
+    extension.wikilinks_title_after_pipe = true;
+    comrak.parse.smart = options.contains(md::Options::ENABLE_SMART_PUNCTUATION);
+
+    let arena = Arena::new();
+    let root = comrak::parse_document(&arena, src, &comrak);
+    // Positions are counted from the end of the front matter
+    let front_matter = root
+        .first_child()
+        .and_then(|node| match &node.data.borrow().value {
+            NodeValue::FrontMatter(text) => Some(text.len()),
+            _ => None,
+        });
+    let mut converter = Converter {
+        lines: Lines::new(src),
+        skipped: front_matter.map_or(0, |len| src[..len].matches('\n').count()),
+        events: vec![],
+    };
+    converter.children(root, false);
+    converter.events
+}
+
+struct Converter<'a> {
+    lines: Lines<'a>,
+    /// Lines of the front matter.
+    skipped: usize,
+    events: Vec<(Event<'static>, Range<usize>)>,
+}
+
+impl Converter<'_> {
+    fn range(&self, sourcepos: Sourcepos) -> Range<usize> {
+        let start = self
+            .lines
+            .offset(sourcepos.start.line + self.skipped, sourcepos.start.column);
+        // The end is the last character of the node
+        let end = self
+            .lines
+            .offset(sourcepos.end.line + self.skipped, sourcepos.end.column + 1);
+        start..end.max(start)
+    }
+
+    fn push(&mut self, event: Event<'static>, range: &Range<usize>) {
+        self.events.push((event, range.clone()));
+    }
+
+    /// Converts the children of `node`, leaving out the paragraphs of tight
+    /// lists.
+    fn children<'n>(&mut self, node: &'n AstNode<'n>, tight: bool) {
+        for child in node.children() {
+            self.node(child, tight);
+        }
+    }
+
+    fn container<'n>(&mut self, node: &'n AstNode<'n>, tag: Tag<'static>, range: &Range<usize>) {
+        let end = tag.to_end();
+        self.push(Event::Start(tag), range);
+        self.children(node, false);
+        self.push(Event::End(end), range);
+    }
+
+    fn node<'n>(&mut self, node: &'n AstNode<'n>, tight: bool) {
+        let ast = node.data.borrow();
+        let range = self.range(ast.sourcepos);
+        match &ast.value {
+            NodeValue::FrontMatter(text) => {
+                let kind = match text.starts_with("+++") {
+                    true => MetadataBlockKind::PlusesStyle,
+                    false => MetadataBlockKind::YamlStyle,
+                };
+                let inner = text
+                    .trim_end()
+                    .split_once('\n')
+                    .and_then(|(_, rest)| rest.rsplit_once('\n'))
+                    .map_or("", |(inner, _)| inner);
+                let range = 0..text.len();
+                self.push(Event::Start(Tag::MetadataBlock(kind)), &range);
+                self.push(Event::Text(owned(format!("{}\n", inner))), &range);
+                self.push(Event::End(TagEnd::MetadataBlock(kind)), &range);
+            }
+            NodeValue::BlockQuote | NodeValue::MultilineBlockQuote(_) => {
+                self.container(node, Tag::BlockQuote(None), &range)
+            }
+            NodeValue::List(list) => {
+                let start = (list.list_type == ListType::Ordered).then_some(list.start as u64);
+                self.push(Event::Start(Tag::List(start)), &range);
+                self.children(node, list.tight);
+                self.push(Event::End(TagEnd::List(start.is_some())), &range);
+            }
+            NodeValue::Item(_) | NodeValue::TaskItem(_) => {
+                self.push(Event::Start(Tag::Item), &range);
+                if let NodeValue::TaskItem(symbol) = &ast.value {
+                    self.push(Event::TaskListMarker(symbol.is_some()), &range);
+                }
+                self.children(node, tight);
+                self.push(Event::End(TagEnd::Item), &range);
+            }
+            NodeValue::DescriptionList => self.container(node, Tag::DefinitionList, &range),
+            NodeValue::DescriptionTerm | NodeValue::DescriptionDetails => {
+                let tag = match ast.value {
+                    NodeValue::DescriptionTerm => Tag::DefinitionListTitle,
+                    _ => Tag::DefinitionListDefinition,
+                };
+                self.push(Event::Start(tag.clone()), &range);
+                self.children(node, true);
+                self.push(Event::End(tag.to_end()), &range);
+            }
+            NodeValue::CodeBlock(code) => {
+                let kind = match code.fenced {
+                    true => CodeBlockKind::Fenced(owned(code.info.clone())),
+                    false => CodeBlockKind::Indented,
+                };
+                self.push(Event::Start(Tag::CodeBlock(kind)), &range);
+                if !code.literal.is_empty() {
+                    self.push(Event::Text(owned(code.literal.clone())), &range);
+                }
+                self.push(Event::End(TagEnd::CodeBlock), &range);
+            }
+            NodeValue::HtmlBlock(html) => {
+                self.push(Event::Start(Tag::HtmlBlock), &range);
+                self.push(Event::Html(owned(html.literal.clone())), &range);
+                self.push(Event::End(TagEnd::HtmlBlock), &range);
+            }
+            NodeValue::Paragraph if tight => self.children(node, false),
+            NodeValue::Paragraph => self.container(node, Tag::Paragraph, &range),
+            NodeValue::Heading(heading) => {
+                let level =
+                    HeadingLevel::try_from(heading.level as usize).unwrap_or(HeadingLevel::H6);
+                let tag = Tag::Heading {
+                    level,
+                    id: None,
+                    classes: vec![],
+                    attrs: vec![],
+                };
+                self.container(node, tag, &range)
+            }
+            NodeValue::ThematicBreak => self.push(Event::Rule, &range),
+            NodeValue::FootnoteDefinition(definition) => {
+                let tag = Tag::FootnoteDefinition(owned(definition.name.clone()));
+                self.container(node, tag, &range)
+            }
+            NodeValue::Table(table) => {
+                let alignments = table
+                    .alignments
+                    .iter()
+                    .map(|alignment| match alignment {
+                        TableAlignment::None => Alignment::None,
+                        TableAlignment::Left => Alignment::Left,
+                        TableAlignment::Center => Alignment::Center,
+                        TableAlignment::Right => Alignment::Right,
+                    })
+                    .collect();
+                self.container(node, Tag::Table(alignments), &range)
+            }
+            NodeValue::TableRow(true) => self.container(node, Tag::TableHead, &range),
+            NodeValue::TableRow(false) => self.container(node, Tag::TableRow, &range),
+            NodeValue::TableCell => self.container(node, Tag::TableCell, &range),
+            NodeValue::Text(text) => self.push(Event::Text(owned(text.clone())), &range),
+            NodeValue::SoftBreak => self.push(Event::SoftBreak, &range),
+            NodeValue::LineBreak => self.push(Event::HardBreak, &range),
+            NodeValue::Code(code) => self.push(Event::Code(owned(code.literal.clone())), &range),
+            NodeValue::HtmlInline(html) => {
+                self.push(Event::InlineHtml(owned(html.clone())), &range)
+            }
+            NodeValue::Emph => self.container(node, Tag::Emphasis, &range),
+            NodeValue::Strong => self.container(node, Tag::Strong, &range),
+            NodeValue::Strikethrough => self.container(node, Tag::Strikethrough, &range),
+            NodeValue::Superscript => {
+                self.push(Event::InlineHtml("<sup>".into()), &range);
+                self.children(node, false);
+                self.push(Event::InlineHtml("</sup>".into()), &range);
+            }
+            NodeValue::Link(link) => {
+                let source = &self.lines.src[range.clone()];
+                let (link_type, url) = match link.url.strip_prefix("mailto:") {
+                    Some(email) if source.starts_with('<') => (LinkType::Email, email),
+                    _ if source.starts_with('<') => (LinkType::Autolink, link.url.as_str()),
+                    _ => (LinkType::Inline, link.url.as_str()),
+                };
+                let tag = Tag::Link {
+                    link_type,
+                    dest_url: owned(url.to_string()),
+                    title: owned(link.title.clone()),
+                    id: "".into(),
+                };
+                self.container(node, tag, &range)
+            }
+            NodeValue::Image(link) => {
+                let tag = Tag::Image {
+                    link_type: LinkType::Inline,
+                    dest_url: owned(link.url.clone()),
+                    title: owned(link.title.clone()),
+                    id: "".into(),
+                };
+                self.container(node, tag, &range)
+            }
+            NodeValue::FootnoteReference(reference) => self.push(
+                Event::FootnoteReference(owned(reference.name.clone())),
+                &range,
+            ),
+            NodeValue::Math(math) => {
+                let literal = owned(math.literal.clone());
+                match math.display_math {
+                    true => self.push(Event::DisplayMath(literal), &range),
+                    false => self.push(Event::InlineMath(literal), &range),
+                }
+            }
+            // Wiki links are resolved later, like those read by the other
+            // parsers
+            NodeValue::WikiLink(link) => {
+                let label = node
+                    .descendants()
+                    .filter_map(|node| match &node.data.borrow().value {
+                        NodeValue::Text(text) => Some(text.clone()),
+                        _ => None,
+                    })
+                    .collect::<String>();
+                let text = match label == link.url {
+                    true => format!("[[{}]]", link.url),
+                    false => format!("[[{}|{}]]", link.url, label),
+                };
+                self.push(Event::Text(owned(text)), &range)
+            }
+            NodeValue::EscapedTag(text) => self.push(Event::Text(owned(text.clone())), &range),
+            _ => self.children(node, tight),
+        }
+    }
+}
+
+fn owned(text: String) -> CowStr<'static> {
+    CowStr::from(text)
+}
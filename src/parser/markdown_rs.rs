@@ -0,0 +1,361 @@
+//! Reading notes with markdown-rs, as MDX.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use markdown::mdast::{AlignKind, Node, ReferenceKind};
+use markdown::message::Place;
+use markdown::unist::Position;
+use markdown::{Constructs, ParseOptions};
+use pulldown_cmark::{
+    self as md, Alignment, CodeBlockKind, CowStr, Event, HeadingLevel, LinkType, MetadataBlockKind,
+    Tag, TagEnd,
+};
+
+use super::{Error, Lines};
+
+pub fn parse(
+    src: &str,
+    options: md::Options,
+) -> Result<Vec<(Event<'static>, Range<usize>)>, Error> {
+    let footnotes = options.contains(md::Options::ENABLE_FOOTNOTES);
+    let math = options.contains(md::Options::ENABLE_MATH);
+    let parse = ParseOptions {
+        constructs: Constructs {
+            gfm_autolink_literal: true,
+            gfm_footnote_definition: footnotes,
+            gfm_label_start_footnote: footnotes,
+            gfm_strikethrough: options.contains(md::Options::ENABLE_STRIKETHROUGH),
+            gfm_table: options.contains(md::Options::ENABLE_TABLES),
+            gfm_task_list_item: options.contains(md::Options::ENABLE_TASKLISTS),
+            frontmatter: options.intersects(
+                md::Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+                    | md::Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS,
+            ),
+            math_flow: math,
+            math_text: math,
+            ..Constructs::mdx()
+        },
+        ..ParseOptions::default()
+    };
+    let root = markdown::to_mdast(src, &parse).map_err(|message| {
+        let range = match message.place.as_deref() {
+            Some(Place::Position(position)) => position.start.offset..position.end.offset,
+            Some(Place::Point(point)) => point.offset..point.offset + 1,
+            None => 0..0,
+        };
+        Error {
+            message: message.reason,
+            range,
+        }
+    })?;
+
+    let mut converter = Converter {
+        lines: Lines::new(src),
+        definitions: HashMap::new(),
+        events: vec![],
+    };
+    converter.definitions(&root);
+    converter.children(&root, false);
+    Ok(converter.events)
+}
+
+struct Converter<'a> {
+    lines: Lines<'a>,
+    /// Destinations and titles of the link reference definitions.
+    definitions: HashMap<String, (String, Option<String>)>,
+    events: Vec<(Event<'static>, Range<usize>)>,
+}
+
+impl Converter<'_> {
+    fn definitions(&mut self, node: &Node) {
+        if let Node::Definition(definition) = node {
+            self.definitions.insert(
+                definition.identifier.clone(),
+                (definition.url.clone(), definition.title.clone()),
+            );
+        }
+        for child in node.children().into_iter().flatten() {
+            self.definitions(child);
+        }
+    }
+
+    fn range(&self, position: Option<&Position>) -> Range<usize> {
+        match position {
+            Some(position) => {
+                let start = self.lines.boundary(position.start.offset);
+                start..self.lines.boundary(position.end.offset).max(start)
+            }
+            None => 0..0,
+        }
+    }
+
+    fn push(&mut self, event: Event<'static>, range: &Range<usize>) {
+        self.events.push((event, range.clone()));
+    }
+
+    /// Converts the children of `node`, leaving out the paragraphs of tight
+    /// lists.
+    fn children(&mut self, node: &Node, tight: bool) {
+        for child in node.children().into_iter().flatten() {
+            self.node(child, tight);
+        }
+    }
+
+    fn container(&mut self, node: &Node, tag: Tag<'static>, range: &Range<usize>) {
+        let end = tag.to_end();
+        self.push(Event::Start(tag), range);
+        self.children(node, false);
+        self.push(Event::End(end), range);
+    }
+
+    /// Source of `node` up to its first child and after its last one, or all
+    /// of it if it has none.
+    fn tags(&self, node: &Node, range: &Range<usize>) -> (String, String) {
+        let src = self.lines.src;
+        let children = node
+            .children()
+            .map_or(&[][..], |children| children.as_slice());
+        match (children.first(), children.last()) {
+            (Some(first), Some(last)) => {
+                let first = self.range(first.position());
+                let last = self.range(last.position());
+                (
+                    src[range.start..first.start.max(range.start)].to_string(),
+                    src[last.end.min(range.end)..range.end].to_string(),
+                )
+            }
+            _ => (src[range.clone()].to_string(), String::new()),
+        }
+    }
+
+    fn node(&mut self, node: &Node, tight: bool) {
+        let range = self.range(node.position());
+        match node {
+            Node::Yaml(yaml) => self.metadata(MetadataBlockKind::YamlStyle, &yaml.value, &range),
+            Node::Toml(toml) => self.metadata(MetadataBlockKind::PlusesStyle, &toml.value, &range),
+            Node::BlockQuote(_) => self.container(node, Tag::BlockQuote(None), &range),
+            Node::List(list) => {
+                let start = list.ordered.then(|| list.start.unwrap_or(1) as u64);
+                let loose = list.spread
+                    || list
+                        .children
+                        .iter()
+                        .any(|item| matches!(item, Node::ListItem(item) if item.spread));
+                self.push(Event::Start(Tag::List(start)), &range);
+                self.children(node, !loose);
+                self.push(Event::End(TagEnd::List(list.ordered)), &range);
+            }
+            Node::ListItem(item) => {
+                self.push(Event::Start(Tag::Item), &range);
+                if let Some(checked) = item.checked {
+                    self.push(Event::TaskListMarker(checked), &range);
+                }
+                self.children(node, tight);
+                self.push(Event::End(TagEnd::Item), &range);
+            }
+            Node::Code(code) => {
+                let fenced = self.lines.src[range.clone()].starts_with(['`', '~']);
+                let kind = match fenced {
+                    true => {
+                        let info = [code.lang.as_deref(), code.meta.as_deref()]
+                            .into_iter()
+                            .flatten()
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        CodeBlockKind::Fenced(owned(info))
+                    }
+                    false => CodeBlockKind::Indented,
+                };
+                self.push(Event::Start(Tag::CodeBlock(kind)), &range);
+                if !code.value.is_empty() {
+                    self.push(Event::Text(owned(format!("{}\n", code.value))), &range);
+                }
+                self.push(Event::End(TagEnd::CodeBlock), &range);
+            }
+            Node::Html(html) => self.push(Event::InlineHtml(owned(html.value.clone())), &range),
+            Node::Paragraph(_) if tight => self.children(node, false),
+            Node::Paragraph(_) => self.container(node, Tag::Paragraph, &range),
+            Node::Heading(heading) => {
+                let level =
+                    HeadingLevel::try_from(heading.depth as usize).unwrap_or(HeadingLevel::H6);
+                let tag = Tag::Heading {
+                    level,
+                    id: None,
+                    classes: vec![],
+                    attrs: vec![],
+                };
+                self.container(node, tag, &range)
+            }
+            Node::ThematicBreak(_) => self.push(Event::Rule, &range),
+            Node::FootnoteDefinition(definition) => {
+                let label = definition.label.as_ref().unwrap_or(&definition.identifier);
+                let tag = Tag::FootnoteDefinition(owned(label.clone()));
+                self.container(node, tag, &range)
+            }
+            Node::Table(table) => {
+                let alignments = table
+                    .align
+                    .iter()
+                    .map(|align| match align {
+                        AlignKind::None => Alignment::None,
+                        AlignKind::Left => Alignment::Left,
+                        AlignKind::Center => Alignment::Center,
+                        AlignKind::Right => Alignment::Right,
+                    })
+                    .collect();
+                self.push(Event::Start(Tag::Table(alignments)), &range);
+                for (i, row) in table.children.iter().enumerate() {
+                    let tag = match i {
+                        0 => Tag::TableHead,
+                        _ => Tag::TableRow,
+                    };
+                    let range = self.range(row.position());
+                    self.container(row, tag, &range);
+                }
+                self.push(Event::End(TagEnd::Table), &range);
+            }
+            Node::TableRow(_) => self.container(node, Tag::TableRow, &range),
+            Node::TableCell(_) => self.container(node, Tag::TableCell, &range),
+            Node::Text(text) => self.text(&text.value, &range),
+            Node::Break(_) => self.push(Event::HardBreak, &range),
+            Node::InlineCode(code) => self.push(Event::Code(owned(code.value.clone())), &range),
+            Node::InlineMath(math) => {
+                self.push(Event::InlineMath(owned(math.value.clone())), &range)
+            }
+            Node::Math(math) => self.push(Event::DisplayMath(owned(math.value.clone())), &range),
+            Node::Emphasis(_) => self.container(node, Tag::Emphasis, &range),
+            Node::Strong(_) => self.container(node, Tag::Strong, &range),
+            Node::Delete(_) => self.container(node, Tag::Strikethrough, &range),
+            Node::Link(link) => {
+                let source = &self.lines.src[range.clone()];
+                let link_type = match source.starts_with('<') {
+                    true => LinkType::Autolink,
+                    false => LinkType::Inline,
+                };
+                let tag = Tag::Link {
+                    link_type,
+                    dest_url: owned(link.url.clone()),
+                    title: owned(link.title.clone().unwrap_or_default()),
+                    id: "".into(),
+                };
+                self.container(node, tag, &range)
+            }
+            Node::LinkReference(reference) => {
+                let (url, title) = self.reference(&reference.identifier);
+                let tag = Tag::Link {
+                    link_type: link_type(reference.reference_kind),
+                    dest_url: owned(url),
+                    title: owned(title),
+                    id: owned(reference.label.clone().unwrap_or_default()),
+                };
+                self.container(node, tag, &range)
+            }
+            Node::Image(image) => {
+                let tag = Tag::Image {
+                    link_type: LinkType::Inline,
+                    dest_url: owned(image.url.clone()),
+                    title: owned(image.title.clone().unwrap_or_default()),
+                    id: "".into(),
+                };
+                self.image(tag, &image.alt, &range)
+            }
+            Node::ImageReference(reference) => {
+                let (url, title) = self.reference(&reference.identifier);
+                let tag = Tag::Image {
+                    link_type: link_type(reference.reference_kind),
+                    dest_url: owned(url),
+                    title: owned(title),
+                    id: owned(reference.label.clone().unwrap_or_default()),
+                };
+                self.image(tag, &reference.alt, &range)
+            }
+            Node::FootnoteReference(reference) => {
+                let label = reference.label.as_ref().unwrap_or(&reference.identifier);
+                self.push(Event::FootnoteReference(owned(label.clone())), &range)
+            }
+            // Elements are written as they are, with their content in between
+            Node::MdxJsxFlowElement(_) => {
+                let (open, close) = self.tags(node, &range);
+                self.push(Event::Html(owned(open)), &range);
+                self.children(node, false);
+                if !close.is_empty() {
+                    self.push(Event::Html(owned(close)), &range);
+                }
+            }
+            Node::MdxJsxTextElement(_) => {
+                let (open, close) = self.tags(node, &range);
+                self.push(Event::InlineHtml(owned(open)), &range);
+                self.children(node, false);
+                if !close.is_empty() {
+                    self.push(Event::InlineHtml(owned(close)), &range);
+                }
+            }
+            // Expressions are not evaluated, so they are shown as written
+            Node::MdxFlowExpression(_) => {
+                let source = self.lines.src[range.clone()].to_string();
+                self.push(Event::Start(Tag::Paragraph), &range);
+                self.push(Event::Text(owned(source)), &range);
+                self.push(Event::End(TagEnd::Paragraph), &range);
+            }
+            Node::MdxTextExpression(_) => {
+                let source = self.lines.src[range.clone()].to_string();
+                self.push(Event::Text(owned(source)), &range)
+            }
+            Node::Definition(_) | Node::MdxjsEsm(_) => {}
+            Node::Root(_) => self.children(node, tight),
+        }
+    }
+
+    fn metadata(&mut self, kind: MetadataBlockKind, value: &str, range: &Range<usize>) {
+        self.push(Event::Start(Tag::MetadataBlock(kind)), range);
+        self.push(Event::Text(owned(format!("{}\n", value))), range);
+        self.push(Event::End(TagEnd::MetadataBlock(kind)), range);
+    }
+
+    /// Text with line breaks, which pulldown-cmark reads as soft breaks.
+    fn text(&mut self, text: &str, range: &Range<usize>) {
+        // Lines only have their own ranges if the text is exactly the source
+        let mut offset = (self.lines.src.get(range.clone()) == Some(text)).then_some(range.start);
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.push(Event::SoftBreak, range);
+                offset = offset.map(|offset| offset + 1);
+            }
+            let line_range = offset.map_or(range.clone(), |offset| offset..offset + line.len());
+            if !line.is_empty() {
+                self.push(Event::Text(owned(line.to_string())), &line_range);
+            }
+            offset = offset.map(|offset| offset + line.len());
+        }
+    }
+
+    fn image(&mut self, tag: Tag<'static>, alt: &str, range: &Range<usize>) {
+        self.push(Event::Start(tag), range);
+        if !alt.is_empty() {
+            self.push(Event::Text(owned(alt.to_string())), range);
+        }
+        self.push(Event::End(TagEnd::Image), range);
+    }
+
+    fn reference(&self, identifier: &str) -> (String, String) {
+        let (url, title) = self
+            .definitions
+            .get(identifier)
+            .cloned()
+            .unwrap_or_default();
+        (url, title.unwrap_or_default())
+    }
+}
+
+fn link_type(kind: ReferenceKind) -> LinkType {
+    match kind {
+        ReferenceKind::Full => LinkType::Reference,
+        ReferenceKind::Collapsed => LinkType::Collapsed,
+        ReferenceKind::Shortcut => LinkType::Shortcut,
+    }
+}
+
+fn owned(text: String) -> CowStr<'static> {
+    CowStr::from(text)
+}
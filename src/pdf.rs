@@ -0,0 +1,246 @@
+//! PDF output, laying out the blocks in the columns of the pages.
+//!
+//! Text is set in the standard fonts every PDF reader has, and nothing
+//! depending on the time or machine ends up in the file, so the same input
+//! always gives the same PDF.
+
+mod font;
+mod images;
+mod layout;
+mod pack;
+
+use std::path::Path;
+
+use miniz_oxide::deflate::{compress_to_vec_zlib, CompressionLevel};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str};
+
+use crate::highlight::Theme;
+use crate::{diagnostics, Block};
+use font::Font;
+use images::Images;
+use layout::{Color, Item, Line};
+pub use pack::{PageSize, Sheet};
+use pack::{Segment, PADDING};
+
+const FONT_SIZE: f32 = 8.0;
+/// Smallest font scale, in percent, used to fit blocks on the pages.
+const MIN_SCALE: u32 = 50;
+const SCALE_STEP: u32 = 5;
+
+const BORDER: Color = [0.6, 0.6, 0.6];
+
+/// Renders the blocks into a PDF. Images are looked up in `assets`, the
+/// output directory they have been copied to, and code is highlighted with
+/// the colors of `theme`.
+///
+/// If the blocks have to fit on a number of pages, the text is shrunk until
+/// they do. Blocks that do not fit even then are reported and put on
+/// additional pages.
+pub fn render(blocks: &[Block], assets: &Path, sheet: &Sheet, theme: &Theme) -> Vec<u8> {
+    let mut images = Images::default();
+    let mut scale = 100;
+    let (lines, segments) = loop {
+        let lines = blocks
+            .iter()
+            .map(|block| {
+                layout::layout(
+                    &block.events,
+                    sheet.column_width() - 2.0 * PADDING,
+                    sheet.column_height() - 2.0 * PADDING,
+                    FONT_SIZE * scale as f32 / 100.0,
+                    assets,
+                    &mut images,
+                    theme,
+                )
+            })
+            .collect::<Vec<_>>();
+        let segments = pack::pack(&lines, sheet);
+        match sheet.pages {
+            Some(pages) if pack::page_count(&segments) > pages && scale > MIN_SCALE => {
+                scale -= SCALE_STEP
+            }
+            _ => break (lines, segments),
+        }
+    };
+
+    if let Some(pages) = sheet.pages {
+        let overflow = blocks
+            .iter()
+            .zip(&segments)
+            .filter(|(_, segments)| segments.iter().any(|segment| segment.page >= pages))
+            .map(|(block, _)| format!("`{}`", block.id))
+            .collect::<Vec<_>>();
+        if !overflow.is_empty() {
+            diagnostics::warning(format_args!(
+                "blocks do not fit on {} page(s) even at {}% text size: {}",
+                pages,
+                scale,
+                overflow.join(", ")
+            ));
+        }
+    }
+
+    let mut pages = (0..pack::page_count(&segments))
+        .map(|_| Content::new())
+        .collect::<Vec<_>>();
+    for ((block, lines), segments) in blocks.iter().zip(&lines).zip(&segments) {
+        let border = block
+            .meta
+            .color
+            .as_deref()
+            .and_then(parse_color)
+            .unwrap_or(BORDER);
+        for segment in segments {
+            draw_segment(&mut pages[segment.page], sheet, segment, lines, border);
+        }
+    }
+
+    write(pages, &images, sheet)
+}
+
+/// Draws the lines of a segment and the border around them.
+fn draw_segment(
+    content: &mut Content,
+    sheet: &Sheet,
+    segment: &Segment,
+    lines: &[Line],
+    border: Color,
+) {
+    let left = sheet.column_left(segment.column);
+    let top = sheet.column_top();
+
+    let mut y = segment.top + PADDING;
+    for line in &lines[segment.lines.clone()] {
+        draw_line(content, line, left + PADDING, top - y);
+        y += line.height;
+    }
+
+    content.set_stroke_rgb(border[0], border[1], border[2]);
+    content.set_line_width(0.75);
+    content.rect(
+        left,
+        top - segment.bottom,
+        sheet.column_width(),
+        segment.bottom - segment.top,
+    );
+    content.stroke();
+}
+
+/// Draws a line with its top left corner at `left`, `top`.
+fn draw_line(content: &mut Content, line: &Line, left: f32, top: f32) {
+    for item in &line.items {
+        match item {
+            Item::Text {
+                x,
+                y,
+                font,
+                size,
+                color,
+                text,
+            } => {
+                content.begin_text();
+                content.set_font(Name(font.resource()), *size);
+                content.set_fill_rgb(color[0], color[1], color[2]);
+                content.next_line(left + x, top - y);
+                content.show(Str(&font::encode(text)));
+                content.end_text();
+            }
+            Item::Rect {
+                x,
+                y,
+                width,
+                height,
+                color,
+            } => {
+                content.set_fill_rgb(color[0], color[1], color[2]);
+                content.rect(left + x, top - y - height, *width, *height);
+                content.fill_nonzero();
+            }
+            Item::Image {
+                x,
+                y,
+                width,
+                height,
+                image,
+            } => {
+                content.save_state();
+                content.transform([*width, 0.0, 0.0, *height, left + x, top - y - height]);
+                content.x_object(Name(image_name(*image).as_bytes()));
+                content.restore_state();
+            }
+        }
+    }
+}
+
+fn image_name(image: usize) -> String {
+    format!("Im{}", image + 1)
+}
+
+/// Parses colors like `#1f6feb` from the front matter.
+fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(hex.get(i..i + 2)?, 16)
+            .ok()
+            .map(|value| value as f32 / 255.0)
+    };
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn write(pages: Vec<Content>, images: &Images, sheet: &Sheet) -> Vec<u8> {
+    let mut pdf = Pdf::new();
+    let mut next = Ref::new(1);
+    let mut alloc = || next.bump();
+
+    let catalog_id = alloc();
+    let page_tree_id = alloc();
+    let font_ids = Font::ALL.map(|_| alloc());
+    let image_ids = (0..images.len()).map(|_| alloc()).collect::<Vec<_>>();
+    let page_ids = pages.iter().map(|_| alloc()).collect::<Vec<_>>();
+
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id)
+        .kids(page_ids.iter().copied())
+        .count(page_ids.len() as i32);
+
+    for (font, id) in Font::ALL.iter().zip(font_ids) {
+        pdf.type1_font(id)
+            .base_font(Name(font.base_font()))
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+
+    for (index, id) in image_ids.iter().enumerate() {
+        images.write(index, &mut pdf, *id, &mut alloc);
+    }
+
+    for (content, page_id) in pages.into_iter().zip(page_ids) {
+        let content_id = alloc();
+        let mut page = pdf.page(page_id);
+        let (width, height) = sheet.page();
+        page.media_box(Rect::new(0.0, 0.0, width, height));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        let mut resources = page.resources();
+        let mut fonts = resources.fonts();
+        for (font, id) in Font::ALL.iter().zip(font_ids) {
+            fonts.pair(Name(font.resource()), id);
+        }
+        fonts.finish();
+        let mut x_objects = resources.x_objects();
+        for (index, id) in image_ids.iter().enumerate() {
+            x_objects.pair(Name(image_name(index).as_bytes()), *id);
+        }
+        x_objects.finish();
+        resources.finish();
+        page.finish();
+
+        let level = CompressionLevel::DefaultLevel as u8;
+        pdf.stream(content_id, &compress_to_vec_zlib(&content.finish(), level))
+            .filter(Filter::FlateDecode);
+    }
+
+    pdf.finish()
+}
@@ -0,0 +1,102 @@
+//! Images shown in the PDF.
+
+use std::path::{Path, PathBuf};
+
+use image::{ColorType, DynamicImage, GenericImageView, ImageFormat};
+use miniz_oxide::deflate::{compress_to_vec_zlib, CompressionLevel};
+use pdf_writer::{Filter, Finish, Pdf, Ref};
+
+struct Image {
+    path: PathBuf,
+    data: Vec<u8>,
+    format: ImageFormat,
+    decoded: DynamicImage,
+}
+
+/// Images used in the PDF, each added once.
+#[derive(Default)]
+pub struct Images {
+    images: Vec<Image>,
+}
+
+impl Images {
+    /// Loads the image at `path` unless it was added before, and returns its
+    /// index and size in pixels. Returns `None` if it is not an image we can
+    /// read.
+    pub fn add(&mut self, path: &Path) -> Option<(usize, u32, u32)> {
+        let index = match self.images.iter().position(|image| image.path == path) {
+            Some(index) => index,
+            None => {
+                let data = std::fs::read(path).ok()?;
+                let format = image::guess_format(&data).ok()?;
+                let decoded = image::load_from_memory_with_format(&data, format).ok()?;
+                self.images.push(Image {
+                    path: path.to_path_buf(),
+                    data,
+                    format,
+                    decoded,
+                });
+                self.images.len() - 1
+            }
+        };
+        let (width, height) = self.images[index].decoded.dimensions();
+        Some((index, width, height))
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Writes the image `index` as `id`. Images with transparency need a
+    /// second object for their soft mask, which is allocated with `alloc`.
+    pub fn write(&self, index: usize, pdf: &mut Pdf, id: Ref, alloc: &mut impl FnMut() -> Ref) {
+        let image = &self.images[index];
+        let (width, height) = image.decoded.dimensions();
+        let level = CompressionLevel::DefaultLevel as u8;
+
+        // JPEGs can be embedded as they are
+        let gray = image.decoded.color() == ColorType::L8;
+        let (filter, encoded, gray) = match image.format {
+            ImageFormat::Jpeg if gray || image.decoded.color() == ColorType::Rgb8 => {
+                (Filter::DctDecode, image.data.clone(), gray)
+            }
+            _ => (
+                Filter::FlateDecode,
+                compress_to_vec_zlib(image.decoded.to_rgb8().as_raw(), level),
+                false,
+            ),
+        };
+        let mask = image.decoded.color().has_alpha().then(|| {
+            let alphas = image
+                .decoded
+                .pixels()
+                .map(|(_, _, pixel)| pixel.0[3])
+                .collect::<Vec<_>>();
+            (alloc(), compress_to_vec_zlib(&alphas, level))
+        });
+
+        let mut xobject = pdf.image_xobject(id, &encoded);
+        xobject.filter(filter);
+        xobject.width(width as i32);
+        xobject.height(height as i32);
+        if gray {
+            xobject.color_space().device_gray();
+        } else {
+            xobject.color_space().device_rgb();
+        }
+        xobject.bits_per_component(8);
+        if let Some((mask_id, _)) = &mask {
+            xobject.s_mask(*mask_id);
+        }
+        xobject.finish();
+
+        if let Some((mask_id, alphas)) = mask {
+            let mut s_mask = pdf.image_xobject(mask_id, &alphas);
+            s_mask.filter(Filter::FlateDecode);
+            s_mask.width(width as i32);
+            s_mask.height(height as i32);
+            s_mask.color_space().device_gray();
+            s_mask.bits_per_component(8);
+        }
+    }
+}
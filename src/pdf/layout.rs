@@ -0,0 +1,798 @@
+//! Layout of the events of a block into lines of a fixed width.
+
+use std::path::Path;
+
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Tag, TagEnd};
+
+use super::font::Font;
+use super::images::Images;
+use crate::code;
+use crate::diagnostics;
+use crate::highlight::{self, Theme, Token};
+
+/// Line height, relative to the font size.
+const LEADING: f32 = 1.25;
+/// Indentation of lists and quotes, relative to the font size.
+const INDENT: f32 = 1.5;
+/// Padding of table cells, relative to the font size.
+const CELL_PADDING: f32 = 0.3;
+/// Pixels of images per point, images are shown at 96 dpi if they fit.
+const PIXELS_PER_POINT: f32 = 96.0 / 72.0;
+
+pub type Color = [f32; 3];
+
+const BLACK: Color = [0.0, 0.0, 0.0];
+const LINK: Color = [0.0, 0.25, 0.6];
+const GRAY: Color = [0.93, 0.93, 0.93];
+const RULE: Color = [0.6, 0.6, 0.6];
+const HIGHLIGHT: Color = [1.0, 0.95, 0.7];
+
+/// Part of a block that is never broken across columns, usually a line of
+/// text. Coordinates are relative to the top left corner of the line.
+#[derive(Debug, Default)]
+pub struct Line {
+    pub height: f32,
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug)]
+pub enum Item {
+    /// Text starting at `x`, with its baseline `y` below the top.
+    Text {
+        x: f32,
+        y: f32,
+        font: Font,
+        size: f32,
+        color: Color,
+        text: String,
+    },
+    /// Filled rectangle with its top left corner at `x`, `y`.
+    Rect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        color: Color,
+    },
+    /// Image with its top left corner at `x`, `y`, by index into [`Images`].
+    Image {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        image: usize,
+    },
+}
+
+impl Item {
+    fn shift(&mut self, dx: f32, dy: f32) {
+        match self {
+            Item::Text { x, y, .. } | Item::Rect { x, y, .. } | Item::Image { x, y, .. } => {
+                *x += dx;
+                *y += dy;
+            }
+        }
+    }
+}
+
+/// Run of text in one style.
+#[derive(Debug, Clone)]
+struct Span {
+    text: String,
+    font: Font,
+    size: f32,
+    color: Color,
+}
+
+/// Text between two places a line can be broken at.
+#[derive(Debug, Default)]
+struct Word {
+    spans: Vec<Span>,
+    /// Whether the word is preceded by a space.
+    space: bool,
+    /// Whether the word starts a new line.
+    newline: bool,
+}
+
+impl Word {
+    fn width(&self) -> f32 {
+        self.spans
+            .iter()
+            .map(|span| span.font.width(&span.text, span.size))
+            .sum()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    rows: Vec<(bool, Vec<Vec<Word>>)>,
+    head: bool,
+}
+
+/// Lays out the events of a block in lines of the given width. Images are
+/// scaled down to fit into `width` and `height`, and added to `images`.
+///
+/// Images are looked up relative to `assets`, the output directory they have
+/// been copied to. Code is highlighted with the colors of `theme`.
+pub fn layout(
+    events: &[Event],
+    width: f32,
+    height: f32,
+    font_size: f32,
+    assets: &Path,
+    images: &mut Images,
+    theme: &Theme,
+) -> Vec<Line> {
+    let mut layout = Layout {
+        width,
+        height,
+        font_size,
+        size: font_size,
+        assets,
+        images,
+        theme,
+        lines: vec![],
+        words: vec![],
+        space: false,
+        newline: false,
+        bold: 0,
+        italic: 0,
+        link: 0,
+        indent: 0.0,
+        quotes: vec![],
+        lists: vec![],
+        marker: None,
+        code: None,
+        image: None,
+        table: None,
+        in_metadata_block: false,
+    };
+    for event in events {
+        layout.event(event);
+    }
+    layout.flush();
+    layout.lines
+}
+
+struct Layout<'a> {
+    width: f32,
+    height: f32,
+    font_size: f32,
+    /// Size of the text being read.
+    size: f32,
+    assets: &'a Path,
+    images: &'a mut Images,
+    theme: &'a Theme,
+    lines: Vec<Line>,
+    /// Text of the paragraph being read.
+    words: Vec<Word>,
+    /// Whether the next word is preceded by a space.
+    space: bool,
+    /// Whether the next word starts a new line.
+    newline: bool,
+    bold: usize,
+    italic: usize,
+    link: usize,
+    indent: f32,
+    /// Positions of the bars of the quotes we are in.
+    quotes: Vec<f32>,
+    /// Next number of the lists we are in, `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    /// Marker of the list item whose first line has not been placed yet.
+    marker: Option<(String, f32)>,
+    /// Options and text of the code block being read.
+    code: Option<(code::Info, String)>,
+    /// Destination and alt text of the image being read.
+    image: Option<(String, String)>,
+    table: Option<Table>,
+    in_metadata_block: bool,
+}
+
+impl Layout<'_> {
+    fn event(&mut self, event: &Event) {
+        if self.in_metadata_block && !matches!(event, Event::End(TagEnd::MetadataBlock(_))) {
+            return;
+        }
+        if let Some((_, alt)) = &mut self.image {
+            match event {
+                Event::End(TagEnd::Image) => self.end_image(),
+                Event::Text(text) | Event::Code(text) => alt.push_str(text),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(code) = &mut self.code {
+            match event {
+                Event::End(TagEnd::CodeBlock) => self.end_code_block(),
+                Event::Text(text) => code.1.push_str(text),
+                _ => {}
+            }
+            return;
+        }
+
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(*tag),
+            Event::Text(text) => self.push_text(text, self.font(), BLACK),
+            Event::Code(text) => self.push_text(text, Font::Mono, BLACK),
+            Event::InlineMath(tex) => self.push_text(tex, Font::Mono, BLACK),
+            Event::DisplayMath(tex) => {
+                self.flush();
+                self.push_text(tex, Font::Mono, BLACK);
+                self.flush();
+            }
+            Event::FootnoteReference(label) => {
+                self.push_text(&format!("[{}]", label), self.font(), LINK)
+            }
+            Event::TaskListMarker(checked) => {
+                let marker = if *checked { "[x]" } else { "[ ]" };
+                self.push_text(marker, Font::Mono, BLACK);
+                self.space = true;
+            }
+            Event::SoftBreak => self.space = true,
+            Event::HardBreak => self.newline = true,
+            Event::Rule => {
+                self.flush();
+                self.paragraph_spacing();
+                self.push_line(Line {
+                    height: self.font_size * 0.5,
+                    items: vec![Item::Rect {
+                        x: 0.0,
+                        y: self.font_size * 0.25,
+                        width: self.width - self.indent,
+                        height: 0.5,
+                        color: RULE,
+                    }],
+                });
+                self.paragraph_spacing();
+            }
+            // Raw HTML can not be rendered, the text of the spans marking
+            // unresolved links is shown as is
+            Event::Html(_) | Event::InlineHtml(_) => {}
+        }
+    }
+
+    fn start(&mut self, tag: &Tag) {
+        match tag {
+            Tag::Paragraph => self.flush(),
+            Tag::Heading { level, .. } => {
+                self.flush();
+                self.paragraph_spacing();
+                self.size = self.font_size
+                    * match level {
+                        HeadingLevel::H1 => 1.4,
+                        HeadingLevel::H2 => 1.2,
+                        _ => 1.05,
+                    };
+                self.bold += 1;
+            }
+            Tag::BlockQuote(_) => {
+                self.flush();
+                self.quotes.push(self.indent);
+                self.indent += self.font_size * INDENT;
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush();
+                let info = match kind {
+                    CodeBlockKind::Fenced(info) => code::Info::parse(info),
+                    CodeBlockKind::Indented => code::Info::default(),
+                };
+                self.code = Some((info, String::new()));
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.lists.push(*start);
+                self.indent += self.font_size * INDENT;
+            }
+            Tag::Item => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => "\u{2022}".to_string(),
+                };
+                self.marker = Some((marker, self.indent - self.font_size * INDENT));
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.flush();
+                self.paragraph_spacing();
+                self.push_text(&format!("[{}]", label), Font::Bold, BLACK);
+                self.space = true;
+            }
+            Tag::DefinitionListTitle => {
+                self.flush();
+                self.bold += 1;
+            }
+            Tag::DefinitionListDefinition => {
+                self.flush();
+                self.indent += self.font_size * INDENT;
+            }
+            Tag::Table(_) => {
+                self.flush();
+                self.paragraph_spacing();
+                self.table = Some(Table::default());
+            }
+            Tag::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.head = true;
+                    table.rows.push((true, vec![]));
+                }
+            }
+            Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push((false, vec![]));
+                }
+            }
+            Tag::TableCell => {
+                self.words.clear();
+                if self.table.as_ref().is_some_and(|table| table.head) {
+                    self.bold += 1;
+                }
+            }
+            Tag::Emphasis => self.italic += 1,
+            Tag::Strong => self.bold += 1,
+            Tag::Link { .. } => self.link += 1,
+            Tag::Image { dest_url, .. } => self.image = Some((dest_url.to_string(), String::new())),
+            Tag::MetadataBlock(_) => self.in_metadata_block = true,
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                self.flush();
+                self.paragraph_spacing();
+            }
+            TagEnd::Heading(_) => {
+                self.flush();
+                self.size = self.font_size;
+                self.bold -= 1;
+            }
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.quotes.pop();
+                self.indent -= self.font_size * INDENT;
+                self.paragraph_spacing();
+            }
+            TagEnd::List(_) => {
+                self.flush();
+                self.lists.pop();
+                self.indent -= self.font_size * INDENT;
+                if self.lists.is_empty() {
+                    self.paragraph_spacing();
+                }
+            }
+            TagEnd::Item | TagEnd::FootnoteDefinition => {
+                self.flush();
+                self.marker = None;
+            }
+            TagEnd::DefinitionListTitle => {
+                self.flush();
+                self.bold -= 1;
+            }
+            TagEnd::DefinitionListDefinition => {
+                self.flush();
+                self.indent -= self.font_size * INDENT;
+            }
+            TagEnd::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.head = false;
+                }
+            }
+            TagEnd::TableCell => {
+                let words = std::mem::take(&mut self.words);
+                self.space = false;
+                self.newline = false;
+                if let Some(table) = &mut self.table {
+                    if table.head {
+                        self.bold -= 1;
+                    }
+                    if let Some((_, cells)) = table.rows.last_mut() {
+                        cells.push(words);
+                    }
+                }
+            }
+            TagEnd::Table => self.end_table(),
+            TagEnd::Emphasis => self.italic -= 1,
+            TagEnd::Strong => self.bold -= 1,
+            TagEnd::Link => self.link -= 1,
+            TagEnd::MetadataBlock(_) => self.in_metadata_block = false,
+            _ => {}
+        }
+    }
+
+    fn font(&self) -> Font {
+        Font::new(self.bold > 0, self.italic > 0)
+    }
+
+    /// Adds text to the paragraph being read.
+    fn push_text(&mut self, text: &str, font: Font, color: Color) {
+        let color = if self.link > 0 { LINK } else { color };
+        let mut rest = text;
+        loop {
+            let trimmed = rest.trim_start();
+            if trimmed.len() < rest.len() {
+                self.space = true;
+            }
+            if trimmed.is_empty() {
+                break;
+            }
+            let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+            if self.space || self.newline || self.words.is_empty() {
+                self.words.push(Word {
+                    spans: vec![],
+                    space: self.space,
+                    newline: self.newline,
+                });
+                self.space = false;
+                self.newline = false;
+            }
+            self.words.last_mut().unwrap().spans.push(Span {
+                text: trimmed[..end].to_string(),
+                font,
+                size: self.size,
+                color,
+            });
+            rest = &trimmed[end..];
+        }
+    }
+
+    /// Breaks the paragraph being read into lines.
+    fn flush(&mut self) {
+        let words = std::mem::take(&mut self.words);
+        self.space = false;
+        self.newline = false;
+        for line in break_lines(words, self.width - self.indent) {
+            self.push_line(line);
+        }
+    }
+
+    /// Adds a line at the current indentation.
+    fn push_line(&mut self, mut line: Line) {
+        for item in &mut line.items {
+            item.shift(self.indent, 0.0);
+        }
+        let baseline = line.items.iter().find_map(|item| match item {
+            Item::Text { y, .. } => Some(*y),
+            _ => None,
+        });
+        if let Some(baseline) = baseline {
+            if let Some((marker, x)) = self.marker.take() {
+                line.items.push(Item::Text {
+                    x,
+                    y: baseline,
+                    font: Font::Regular,
+                    size: self.font_size,
+                    color: BLACK,
+                    text: marker,
+                });
+            }
+        }
+        for x in &self.quotes {
+            line.items.push(Item::Rect {
+                x: x + self.font_size * 0.4,
+                y: 0.0,
+                width: 1.0,
+                height: line.height,
+                color: RULE,
+            });
+        }
+        self.lines.push(line);
+    }
+
+    /// Adds space after a paragraph, unless there is some already.
+    fn paragraph_spacing(&mut self) {
+        if self.lines.last().is_none_or(|line| line.items.is_empty()) {
+            return;
+        }
+        self.push_line(Line {
+            height: self.font_size * 0.4,
+            items: vec![],
+        });
+    }
+
+    fn end_code_block(&mut self) {
+        let (info, code) = self.code.take().unwrap_or_default();
+        let size = self.font_size * 0.9;
+        let width = self.width - self.indent;
+        let height = size * LEADING;
+        if let Some(title) = &info.title {
+            self.push_line(Line {
+                height,
+                items: vec![Item::Text {
+                    x: 0.0,
+                    y: baseline(height, size),
+                    font: Font::Bold,
+                    size,
+                    color: BLACK,
+                    text: title.clone(),
+                }],
+            });
+        }
+        let lines = self.code_lines(info.lang.as_deref(), &code);
+        // Numbers are right aligned in a gutter, followed by a space
+        let gutter = match info.linenos {
+            true => lines.len().to_string().len() + 1,
+            false => 0,
+        };
+        let advance = Font::Mono.width(" ", size);
+        let columns = ((width / advance) as usize).saturating_sub(gutter).max(1);
+        for (number, line) in (1..).zip(lines) {
+            // Long lines are broken anywhere, empty lines are kept
+            let mut chunks: Vec<Vec<(String, Color, usize)>> = vec![vec![]];
+            let mut column = 0;
+            for (text, color) in line {
+                for c in text.chars() {
+                    if column == columns {
+                        chunks.push(vec![]);
+                        column = 0;
+                    }
+                    let chunk = chunks.last_mut().unwrap();
+                    match chunk.last_mut() {
+                        Some((run, last, _)) if *last == color => run.push(c),
+                        _ => chunk.push((c.to_string(), color, gutter + column)),
+                    }
+                    column += 1;
+                }
+            }
+            if info.linenos {
+                let text = format!("{:>1$}", number, gutter - 1);
+                chunks[0].insert(0, (text, RULE, 0));
+            }
+            let background = match info.highlighted(number) {
+                true => HIGHLIGHT,
+                false => GRAY,
+            };
+            for chunk in chunks {
+                let mut items = vec![Item::Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width,
+                    height,
+                    color: background,
+                }];
+                items.extend(chunk.into_iter().map(|(text, color, column)| Item::Text {
+                    x: size * 0.3 + column as f32 * advance,
+                    y: baseline(height, size),
+                    font: Font::Mono,
+                    size,
+                    color,
+                    text,
+                }));
+                self.push_line(Line { height, items });
+            }
+        }
+        self.paragraph_spacing();
+    }
+
+    /// Lines of code as runs of text in one color, highlighted if there is
+    /// a syntax for `lang`.
+    fn code_lines(&self, lang: Option<&str>, code: &str) -> Vec<Vec<(String, Color)>> {
+        let tokens = lang.and_then(|lang| highlight::tokens(code, lang));
+        let highlighted = tokens.is_some();
+        let tokens = tokens.unwrap_or_else(|| {
+            vec![Token {
+                text: code.to_string(),
+                scopes: vec![],
+            }]
+        });
+        highlight::lines(tokens)
+            .into_iter()
+            .map(|line| {
+                line.into_iter()
+                    .filter_map(|token| {
+                        let text = token.text.trim_end_matches(['\n', '\r']);
+                        let color = match highlighted {
+                            true => self.theme.color(&token.scopes),
+                            false => BLACK,
+                        };
+                        (!text.is_empty()).then(|| (text.to_string(), color))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn end_image(&mut self) {
+        let Some((dest_url, alt)) = self.image.take() else {
+            return;
+        };
+        let path = self.assets.join(&dest_url);
+        let Some((image, width, height)) = self.images.add(&path) else {
+            diagnostics::warning(format_args!("can not show image `{}` in PDF", dest_url));
+            self.push_text(&alt, Font::Italic, BLACK);
+            return;
+        };
+
+        self.flush();
+        let (width, height) = (
+            width as f32 / PIXELS_PER_POINT,
+            height as f32 / PIXELS_PER_POINT,
+        );
+        let scale = 1f32
+            .min((self.width - self.indent) / width)
+            .min(self.height / height);
+        self.push_line(Line {
+            height: height * scale,
+            items: vec![Item::Image {
+                x: 0.0,
+                y: 0.0,
+                width: width * scale,
+                height: height * scale,
+                image,
+            }],
+        });
+    }
+
+    fn end_table(&mut self) {
+        let Some(table) = self.table.take() else {
+            return;
+        };
+        let columns = table
+            .rows
+            .iter()
+            .map(|(_, cells)| cells.len())
+            .max()
+            .unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+        let padding = self.font_size * CELL_PADDING;
+        let width = self.width - self.indent;
+
+        // Columns get their natural width if the table fits, and are shrunk
+        // in proportion otherwise
+        let mut widths = vec![0f32; columns];
+        for (_, cells) in &table.rows {
+            for (column, words) in cells.iter().enumerate() {
+                widths[column] = widths[column].max(natural_width(words) + 2.0 * padding);
+            }
+        }
+        let total = widths.iter().sum::<f32>();
+        if total > width {
+            for column in &mut widths {
+                *column *= width / total;
+            }
+        }
+        let total = widths.iter().sum::<f32>();
+
+        for (head, cells) in table.rows {
+            let mut items = vec![];
+            let mut height = 0f32;
+            let mut x = 0.0;
+            for (words, column) in cells.into_iter().zip(&widths) {
+                let mut y = padding;
+                for line in break_lines(words, column - 2.0 * padding) {
+                    for mut item in line.items {
+                        item.shift(x + padding, y);
+                        items.push(item);
+                    }
+                    y += line.height;
+                }
+                height = height.max(y + padding);
+                x += column;
+            }
+            if head {
+                items.insert(
+                    0,
+                    Item::Rect {
+                        x: 0.0,
+                        y: 0.0,
+                        width: total,
+                        height,
+                        color: GRAY,
+                    },
+                );
+            }
+            items.push(Item::Rect {
+                x: 0.0,
+                y: height - 0.5,
+                width: total,
+                height: 0.5,
+                color: RULE,
+            });
+            self.push_line(Line { height, items });
+        }
+        self.paragraph_spacing();
+    }
+}
+
+/// Baseline of text of the given size, centered in a line of `height`.
+fn baseline(height: f32, size: f32) -> f32 {
+    (height + size * 0.7) / 2.0
+}
+
+/// Width of the words if they are not broken.
+fn natural_width(words: &[Word]) -> f32 {
+    words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            let space = match word.spans.first() {
+                Some(span) if i > 0 && word.space => span.font.width(" ", span.size),
+                _ => 0.0,
+            };
+            space + word.width()
+        })
+        .sum()
+}
+
+/// Breaks words into lines of the given width, words that are too long are
+/// broken anywhere.
+fn break_lines(words: Vec<Word>, width: f32) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut line: Vec<(f32, Span)> = vec![];
+    let mut x = 0.0;
+
+    for word in words {
+        let space = match word.spans.first() {
+            Some(span) if word.space && !line.is_empty() => span.font.width(" ", span.size),
+            _ => 0.0,
+        };
+        let fits = x + space + word.width() <= width;
+        if !line.is_empty() && (word.newline || !fits) {
+            lines.push(finish_line(std::mem::take(&mut line)));
+            x = 0.0;
+        } else {
+            x += space;
+        }
+
+        for span in word.spans {
+            let span_width = span.font.width(&span.text, span.size);
+            if x + span_width <= width {
+                line.push((x, span));
+                x += span_width;
+                continue;
+            }
+            let mut piece: Option<(f32, String)> = None;
+            for c in span.text.chars() {
+                let c_width = span.font.width(c.encode_utf8(&mut [0; 4]), span.size);
+                if x + c_width > width && (piece.is_some() || !line.is_empty()) {
+                    if let Some((start, text)) = piece.take() {
+                        line.push((
+                            start,
+                            Span {
+                                text,
+                                ..span.clone()
+                            },
+                        ));
+                    }
+                    lines.push(finish_line(std::mem::take(&mut line)));
+                    x = 0.0;
+                }
+                piece.get_or_insert_with(|| (x, String::new())).1.push(c);
+                x += c_width;
+            }
+            if let Some((start, text)) = piece {
+                line.push((start, Span { text, ..span }));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(finish_line(line));
+    }
+    lines
+}
+
+fn finish_line(spans: Vec<(f32, Span)>) -> Line {
+    let size = spans.iter().map(|(_, span)| span.size).fold(0.0, f32::max);
+    let height = size * LEADING;
+    let y = baseline(height, size);
+    Line {
+        height,
+        items: spans
+            .into_iter()
+            .map(|(x, span)| Item::Text {
+                x,
+                y,
+                font: span.font,
+                size: span.size,
+                color: span.color,
+                text: span.text,
+            })
+            .collect(),
+    }
+}
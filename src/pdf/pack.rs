@@ -0,0 +1,167 @@
+//! Packing of blocks into the columns of pages.
+
+use std::ops::Range;
+
+use serde::Deserialize;
+
+use super::layout::Line;
+
+const MARGIN: f32 = 24.0;
+/// Space between columns.
+const GUTTER: f32 = 10.0;
+/// Space between the border of a block and its content.
+pub const PADDING: f32 = 4.0;
+/// Space between blocks.
+const SPACING: f32 = 6.0;
+
+/// Paper sizes.
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    A3,
+    A4,
+    A5,
+    Letter,
+    Legal,
+}
+
+impl PageSize {
+    /// Width and height in points, upright.
+    fn size(self) -> (f32, f32) {
+        match self {
+            PageSize::A3 => (842.0, 1191.0),
+            PageSize::A4 => (595.0, 842.0),
+            PageSize::A5 => (420.0, 595.0),
+            PageSize::Letter => (612.0, 792.0),
+            PageSize::Legal => (612.0, 1008.0),
+        }
+    }
+}
+
+/// Pages the blocks are packed into.
+#[derive(Debug, Clone, Copy)]
+pub struct Sheet {
+    pub size: PageSize,
+    pub portrait: bool,
+    pub columns: usize,
+    /// Number of pages everything has to fit on, if any.
+    pub pages: Option<usize>,
+}
+
+impl Sheet {
+    /// Width and height of the pages in points.
+    pub fn page(&self) -> (f32, f32) {
+        let (width, height) = self.size.size();
+        if self.portrait {
+            (width, height)
+        } else {
+            (height, width)
+        }
+    }
+
+    pub fn column_width(&self) -> f32 {
+        let columns = self.columns.max(1) as f32;
+        (self.page().0 - 2.0 * MARGIN - (columns - 1.0) * GUTTER) / columns
+    }
+
+    pub fn column_height(&self) -> f32 {
+        self.page().1 - 2.0 * MARGIN
+    }
+
+    /// Left edge of a column.
+    pub fn column_left(&self, column: usize) -> f32 {
+        MARGIN + column as f32 * (self.column_width() + GUTTER)
+    }
+
+    /// Top edge of the columns, in PDF coordinates starting at the bottom.
+    pub fn column_top(&self) -> f32 {
+        self.page().1 - MARGIN
+    }
+}
+
+/// Part of a block in one column. `top` and `bottom` are measured from the top
+/// of the column.
+#[derive(Debug)]
+pub struct Segment {
+    pub page: usize,
+    pub column: usize,
+    pub top: f32,
+    pub bottom: f32,
+    /// Lines of the block in this segment.
+    pub lines: Range<usize>,
+}
+
+/// Packs blocks into columns, filled one after the other in the order of the
+/// blocks. A block is only broken across columns if it does not fit into a
+/// column on its own. Returns the segments of every block.
+pub fn pack(blocks: &[Vec<Line>], sheet: &Sheet) -> Vec<Vec<Segment>> {
+    let column_height = sheet.column_height();
+    let columns = sheet.columns.max(1);
+    let (mut page, mut column, mut y) = (0, 0, 0.0);
+    let next_column = |page: &mut usize, column: &mut usize| {
+        *column += 1;
+        if *column == columns {
+            *column = 0;
+            *page += 1;
+        }
+    };
+
+    blocks
+        .iter()
+        .map(|lines| {
+            let mut segments = vec![];
+
+            let height = lines.iter().map(|line| line.height).sum::<f32>() + 2.0 * PADDING;
+            if y > 0.0 && y + height > column_height && height <= column_height {
+                next_column(&mut page, &mut column);
+                y = 0.0;
+            }
+
+            let mut top = y;
+            let mut start = 0;
+            y += PADDING;
+            for (i, line) in lines.iter().enumerate() {
+                if y + line.height + PADDING > column_height && y > top + PADDING {
+                    segments.push(Segment {
+                        page,
+                        column,
+                        top,
+                        bottom: y + PADDING,
+                        lines: start..i,
+                    });
+                    next_column(&mut page, &mut column);
+                    top = 0.0;
+                    y = PADDING;
+                    start = i;
+                    // Spacing is not needed at the top of a column
+                    if line.items.is_empty() {
+                        start = i + 1;
+                        continue;
+                    }
+                }
+                y += line.height;
+            }
+            y += PADDING;
+            segments.push(Segment {
+                page,
+                column,
+                top,
+                bottom: y,
+                lines: start..lines.len(),
+            });
+            y += SPACING;
+
+            segments
+        })
+        .collect()
+}
+
+/// Number of pages the packed blocks take up.
+pub fn page_count(segments: &[Vec<Segment>]) -> usize {
+    segments
+        .iter()
+        .flatten()
+        .map(|segment| segment.page + 1)
+        .max()
+        .unwrap_or(1)
+}
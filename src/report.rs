@@ -0,0 +1,42 @@
+//! Summary of what a build read and wrote.
+
+use std::path::PathBuf;
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Notes and assets the build read, which are watched for changes.
+    pub sources: Vec<PathBuf>,
+    /// Files written to the output directory.
+    pub written: Vec<PathBuf>,
+    /// Assets copied into the output directory, with their copies.
+    pub copied: Vec<(PathBuf, PathBuf)>,
+    /// Stylesheets, scripts and assets put into a standalone page.
+    pub embedded: Vec<PathBuf>,
+    /// URLs of images and files that are loaded from elsewhere, with the
+    /// note referring to them.
+    pub remote: Vec<(String, PathBuf)>,
+}
+
+impl Report {
+    pub fn print(&self) {
+        for path in &self.written {
+            println!("wrote {}", path.display());
+        }
+        match self.copied.len() {
+            0 => {}
+            1 => println!("copied 1 asset"),
+            copied => println!("copied {} assets", copied),
+        }
+        match self.embedded.len() {
+            0 => {}
+            1 => println!("embedded 1 file into the page"),
+            embedded => println!("embedded {} files into the page", embedded),
+        }
+        if !self.remote.is_empty() {
+            println!("not copied, as they are remote:");
+            for (url, note) in &self.remote {
+                println!("  {} (in {})", url, note.display());
+            }
+        }
+    }
+}
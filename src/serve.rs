@@ -0,0 +1,132 @@
+//! Local preview server, which reloads open pages after every build.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use percent_encoding::percent_decode_str;
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::assets;
+use crate::config::Config;
+use crate::error::Error;
+use crate::watch;
+
+/// Pages ask for the next build here, passing the build they show.
+const RELOAD: &str = "/_reload";
+
+/// Time a page waits for a build before asking again, so connections do not
+/// stay open forever.
+const RELOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of builds so far, notifying waiting pages when it changes.
+#[derive(Default)]
+struct Builds {
+    count: Mutex<u64>,
+    built: Condvar,
+}
+
+/// Serves the output directory on `localhost`, building it again whenever the
+/// inputs change.
+pub fn serve(config: &Config, port: u16) -> Result<(), Error> {
+    let server = Server::http(("127.0.0.1", port)).map_err(Error::Serve)?;
+    println!(
+        "serving {} at http://127.0.0.1:{}/",
+        config.out.display(),
+        port
+    );
+
+    let builds = Arc::new(Builds::default());
+    {
+        let builds = builds.clone();
+        let out = config.out.clone();
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let builds = builds.clone();
+                let out = out.clone();
+                // Reload requests wait for the next build
+                std::thread::spawn(move || respond(request, &out, &builds));
+            }
+        });
+    }
+
+    watch::watch(config, || {
+        *builds.count.lock().unwrap() += 1;
+        builds.built.notify_all();
+    })?;
+    Ok(())
+}
+
+fn respond(request: Request, out: &Path, builds: &Builds) {
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    let path = percent_decode_str(path).decode_utf8_lossy().into_owned();
+
+    let response = if path == RELOAD {
+        let shown = query
+            .strip_prefix("build=")
+            .and_then(|build| build.parse().ok())
+            .unwrap_or(0);
+        let count = builds.count.lock().unwrap();
+        let (count, _) = builds
+            .built
+            .wait_timeout_while(count, RELOAD_TIMEOUT, |count| *count == shown)
+            .unwrap();
+        Response::from_string(count.to_string())
+    } else {
+        let file = file(out, &path);
+        let data = file.as_deref().and_then(|file| std::fs::read(file).ok());
+        match (file, data) {
+            (Some(file), Some(mut data)) => {
+                let content_type = assets::content_type(&file);
+                if content_type.starts_with("text/html") {
+                    data = inject_reload(&data, *builds.count.lock().unwrap());
+                }
+                Response::from_data(data)
+                    .with_header(Header::from_bytes("Content-Type", content_type).unwrap())
+            }
+            _ => Response::from_string("not found").with_status_code(404),
+        }
+    };
+    // The page may have been closed in the meantime
+    let _ = request.respond(response);
+}
+
+/// File in the output directory a request is for, refusing paths leading out
+/// of it.
+fn file(out: &Path, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let file = out.join(relative);
+    if file.is_dir() {
+        Some(file.join("index.html"))
+    } else {
+        Some(file)
+    }
+}
+
+/// Adds a script to the page that waits for the build after `build` and then
+/// reloads it.
+fn inject_reload(html: &[u8], build: u64) -> Vec<u8> {
+    let script = format!(
+        r#"<script>
+(function poll(build) {{
+  fetch("{RELOAD}?build=" + build)
+    .then((response) => response.text())
+    .then((next) => (next == build ? poll(build) : location.reload()))
+    .catch(() => setTimeout(() => poll(build), 1000));
+}})({build});
+</script>
+"#
+    );
+    let html = String::from_utf8_lossy(html);
+    match html.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &html[..end], script, &html[end..]),
+        None => format!("{}{}", html, script),
+    }
+    .into_bytes()
+}
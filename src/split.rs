@@ -0,0 +1,349 @@
+//! Splitting notes into blocks.
+
+use std::collections::VecDeque;
+use std::iter::Peekable;
+use std::ops::Range;
+
+use pulldown_cmark::{Event, HeadingLevel, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+
+/// Marker starting a new block with `--split marker`.
+const MARKER: &str = "<!-- block -->";
+
+/// Where notes are split into blocks.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// At H1 headings.
+    H1,
+    /// At headings up to H2.
+    H2,
+    /// At headings up to H3.
+    H3,
+    /// At headings up to H4.
+    H4,
+    /// At headings up to H5.
+    H5,
+    /// At all headings.
+    H6,
+    /// At thematic breaks (`---`), which are not rendered.
+    Rule,
+    /// At `<!-- block -->` comments on their own line.
+    Marker,
+    /// Not at all, every note is one block.
+    None,
+}
+
+impl Split {
+    fn level(self) -> Option<HeadingLevel> {
+        match self {
+            Split::H1 => Some(HeadingLevel::H1),
+            Split::H2 => Some(HeadingLevel::H2),
+            Split::H3 => Some(HeadingLevel::H3),
+            Split::H4 => Some(HeadingLevel::H4),
+            Split::H5 => Some(HeadingLevel::H5),
+            Split::H6 => Some(HeadingLevel::H6),
+            Split::Rule | Split::Marker | Split::None => None,
+        }
+    }
+}
+
+/// What to do with content before the first split.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Preface {
+    /// Render it as a block of its own.
+    Block,
+    /// Attach it to the first block of the note, after its title.
+    Intro,
+    /// Leave it out.
+    Drop,
+}
+
+/// Iterator adaptor pairing events and their source ranges, as returned by
+/// [`pulldown_cmark::Parser::into_offset_iter`], with the id of the chunk they
+/// belong to.
+///
+/// Chunk 0 is the content before the first split, the preface, which is
+/// handled as configured. Separators like rules or markers are dropped.
+pub struct Chunks<'a, I: Iterator<Item = (Event<'a>, Range<usize>)>> {
+    iter: Peekable<I>,
+    split: Split,
+    chunk: usize,
+    preface: Preface,
+    /// Events of the preface, held back unless it is rendered as a block.
+    preface_events: Vec<(Event<'a>, Range<usize>)>,
+    /// Chunk the preface goes into once its title has been passed.
+    intro_chunk: Option<usize>,
+    dropped_preface: bool,
+    in_metadata_block: bool,
+    /// Number of containers like blockquotes and lists the events are in,
+    /// notes are only split outside of them.
+    depth: usize,
+    queue: VecDeque<(usize, Event<'a>, Range<usize>)>,
+}
+
+impl<'a, I> Chunks<'a, I>
+where
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    pub fn new(iter: I, split: Split, preface: Preface) -> Self {
+        Self {
+            iter: iter.peekable(),
+            split,
+            chunk: 0,
+            preface,
+            preface_events: vec![],
+            intro_chunk: None,
+            dropped_preface: false,
+            in_metadata_block: false,
+            depth: 0,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Whether a preface was left out so far.
+    pub fn dropped_preface(&self) -> bool {
+        self.dropped_preface
+    }
+
+    /// Whether the html block just started is a marker.
+    fn at_marker(&mut self) -> bool {
+        matches!(self.iter.peek(), Some((Event::Html(html), _)) if html.trim() == MARKER)
+    }
+
+    /// Next event with the chunk it belongs to, before handling the preface.
+    fn next_split(&mut self) -> Option<(usize, Event<'a>, Range<usize>)> {
+        loop {
+            let (event, range) = self.iter.next()?;
+            match &event {
+                Event::Start(tag) if is_container(tag) => self.depth += 1,
+                Event::End(tag) if is_container_end(tag) => {
+                    self.depth = self.depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+            if self.depth > 0 {
+                return Some((self.chunk, event, range));
+            }
+            match (&event, self.split) {
+                (Event::Start(Tag::Heading { level, .. }), split)
+                    if split.level().is_some_and(|max| *level <= max) =>
+                {
+                    self.chunk += 1
+                }
+                (Event::Rule, Split::Rule) => {
+                    self.chunk += 1;
+                    continue;
+                }
+                (Event::Start(Tag::HtmlBlock), Split::Marker) if self.at_marker() => {
+                    self.iter.next();
+                    self.chunk += 1;
+                    // Keep the html block if the marker is followed by other html
+                    if matches!(self.iter.peek(), Some((Event::End(TagEnd::HtmlBlock), _))) {
+                        self.iter.next();
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            return Some((self.chunk, event, range));
+        }
+    }
+
+    fn push_intro(&mut self, chunk: usize) {
+        self.intro_chunk = None;
+        let events = std::mem::take(&mut self.preface_events);
+        let start = events.first().map_or(0, |(_, range)| range.start);
+        let end = events.last().map_or(0, |(_, range)| range.end);
+        self.queue.push_back((
+            chunk,
+            Event::Html("<div class=\"intro\">\n".into()),
+            start..start,
+        ));
+        for (event, range) in events {
+            self.queue.push_back((chunk, event, range));
+        }
+        self.queue
+            .push_back((chunk, Event::Html("</div>\n".into()), end..end));
+    }
+}
+
+/// Whether blocks in `tag` are part of it, so a note can not be split there.
+fn is_container(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::BlockQuote(_)
+            | Tag::List(_)
+            | Tag::Item
+            | Tag::FootnoteDefinition(_)
+            | Tag::DefinitionList
+            | Tag::DefinitionListTitle
+            | Tag::DefinitionListDefinition
+    )
+}
+
+fn is_container_end(tag: &TagEnd) -> bool {
+    matches!(
+        tag,
+        TagEnd::BlockQuote(_)
+            | TagEnd::List(_)
+            | TagEnd::Item
+            | TagEnd::FootnoteDefinition
+            | TagEnd::DefinitionList
+            | TagEnd::DefinitionListTitle
+            | TagEnd::DefinitionListDefinition
+    )
+}
+
+impl<'a, I> Iterator for Chunks<'a, I>
+where
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    type Item = (usize, Event<'a>, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.queue.pop_front() {
+                return Some(item);
+            }
+            let Some((chunk, event, range)) = self.next_split() else {
+                // Without any split the preface is the whole note
+                let events = std::mem::take(&mut self.preface_events);
+                self.queue
+                    .extend(events.into_iter().map(|(event, range)| (0, event, range)));
+                return self.queue.pop_front();
+            };
+
+            match &event {
+                Event::Start(Tag::MetadataBlock(_)) => self.in_metadata_block = true,
+                Event::End(TagEnd::MetadataBlock(_)) => {
+                    self.in_metadata_block = false;
+                    return Some((chunk, event, range));
+                }
+                _ => {}
+            }
+            if chunk == 0 && self.preface != Preface::Block && !self.in_metadata_block {
+                self.preface_events.push((event, range));
+                continue;
+            }
+
+            if chunk > 0 && !self.preface_events.is_empty() && self.intro_chunk.is_none() {
+                match self.preface {
+                    Preface::Block => {}
+                    Preface::Drop => {
+                        self.preface_events.clear();
+                        self.dropped_preface = true;
+                    }
+                    Preface::Intro => match event {
+                        Event::Start(Tag::Heading { .. }) => self.intro_chunk = Some(chunk),
+                        _ => self.push_intro(chunk),
+                    },
+                }
+            }
+            if matches!(event, Event::End(TagEnd::Heading(_))) && self.intro_chunk == Some(chunk) {
+                self.queue.push_back((chunk, event, range));
+                self.push_intro(chunk);
+                continue;
+            }
+            if !self.queue.is_empty() {
+                self.queue.push_back((chunk, event, range));
+                continue;
+            }
+            return Some((chunk, event, range));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark as md;
+
+    use super::*;
+    use crate::parser::{self, Parser};
+
+    /// Text of the note with the chunk it is in.
+    fn chunks(src: &str, split: Split, preface: Preface) -> Vec<(usize, String)> {
+        let events = parser::parse(src, Parser::PulldownCmark, md::Options::all());
+        Chunks::new(events.into_iter(), split, preface)
+            .filter_map(|(chunk, event, _)| match event {
+                Event::Text(text) => Some((chunk, text.trim().to_string())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn texts(chunks: &[(usize, &str)]) -> Vec<(usize, String)> {
+        chunks
+            .iter()
+            .map(|&(chunk, text)| (chunk, text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn headings() {
+        let src = "# A\na\n## B\nb\n### C\nc\n";
+        assert_eq!(
+            chunks(src, Split::H2, Preface::Block),
+            texts(&[(1, "A"), (1, "a"), (2, "B"), (2, "b"), (2, "C"), (2, "c")])
+        );
+        assert_eq!(
+            chunks(src, Split::None, Preface::Block),
+            texts(&[(0, "A"), (0, "a"), (0, "B"), (0, "b"), (0, "C"), (0, "c")])
+        );
+    }
+
+    #[test]
+    fn rules_and_markers() {
+        assert_eq!(
+            chunks("a\n\n---\n\nb\n", Split::Rule, Preface::Block),
+            texts(&[(0, "a"), (1, "b")])
+        );
+        assert_eq!(
+            chunks("a\n\n<!-- block -->\n\nb\n", Split::Marker, Preface::Block),
+            texts(&[(0, "a"), (1, "b")])
+        );
+    }
+
+    #[test]
+    fn not_inside_containers() {
+        assert_eq!(
+            chunks("# A\n\n> ## S2\n> b\n", Split::H2, Preface::Block),
+            texts(&[(1, "A"), (1, "S2"), (1, "b")])
+        );
+        assert_eq!(
+            chunks("a\n\n> b\n>\n> ---\n> c\n", Split::Rule, Preface::Block),
+            texts(&[(0, "a"), (0, "b"), (0, "c")])
+        );
+        assert_eq!(
+            chunks(
+                "a\n\n- b\n\n  <!-- block -->\n\n  c\n",
+                Split::Marker,
+                Preface::Block
+            ),
+            texts(&[(0, "a"), (0, "b"), (0, "c")])
+        );
+        // Splitting goes on after the container
+        assert_eq!(
+            chunks("> # Q\n\n# A\n", Split::H1, Preface::Block),
+            texts(&[(0, "Q"), (1, "A")])
+        );
+    }
+
+    #[test]
+    fn preface() {
+        let src = "intro\n\n# A\na\n";
+        assert_eq!(
+            chunks(src, Split::H1, Preface::Block),
+            texts(&[(0, "intro"), (1, "A"), (1, "a")])
+        );
+        assert_eq!(
+            chunks(src, Split::H1, Preface::Intro),
+            texts(&[(1, "A"), (1, "intro"), (1, "a")])
+        );
+        let events = parser::parse(src, Parser::PulldownCmark, md::Options::empty());
+        let mut dropped = Chunks::new(events.into_iter(), Split::H1, Preface::Drop);
+        assert!(dropped.by_ref().all(|(chunk, _, _)| chunk == 1));
+        assert!(dropped.dropped_preface());
+    }
+}
@@ -0,0 +1,323 @@
+//! Single file output, with the stylesheets, scripts and assets of the page
+//! put into the page itself.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use percent_encoding::percent_decode_str;
+use pulldown_cmark_escape::escape_href;
+
+use crate::assets;
+use crate::diagnostics;
+use crate::links;
+
+/// Replaces the URLs of the `assets`, paired with the paths they would be
+/// copied to, by `data:` URIs, and inlines the stylesheets and scripts the
+/// page loads from `templates`, or the `stylesheets` the build writes itself
+/// by their URL. Returns the page and the files put into it.
+pub fn inline(
+    page: &str,
+    templates: &Path,
+    assets: &[(PathBuf, PathBuf)],
+    stylesheets: &[(&str, String)],
+) -> (String, Vec<PathBuf>) {
+    let mut page = page.to_string();
+    let mut embedded = vec![];
+    for (path, relative) in assets {
+        let mut url = String::new();
+        escape_href(&mut url, &assets::url(relative)).unwrap();
+        let references = ["src", "href"]
+            .iter()
+            .flat_map(|attribute| {
+                // With or without a fragment, `file.pdf#page=2`
+                [
+                    format!("{}=\"{}\"", attribute, url),
+                    format!("{}=\"{}#", attribute, url),
+                ]
+            })
+            .filter(|reference| page.contains(reference.as_str()))
+            .collect::<Vec<_>>();
+        if references.is_empty() {
+            continue;
+        }
+        let Some(uri) = data_uri(path) else {
+            continue;
+        };
+        for reference in references {
+            let (attribute, rest) = reference.split_once('=').unwrap();
+            let end = &rest[1 + url.len()..];
+            page = page.replace(&reference, &format!("{}=\"{}{}", attribute, uri, end));
+        }
+        embedded.push(path.clone());
+    }
+
+    let mut out = String::with_capacity(page.len());
+    let mut rest = page.as_str();
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(tag) = Tag::parse(rest) else {
+            out.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+        let end = match tag.name.as_str() {
+            "link" => {
+                out.push_str(&link(&tag, rest, templates, stylesheets, &mut embedded));
+                tag.end
+            }
+            "script" => {
+                // The contents of scripts are not markup
+                let close = find_ignore_case(&rest[tag.end..], "</script")
+                    .map_or(rest.len(), |close| tag.end + close);
+                match script(&tag, rest, templates, &mut embedded) {
+                    Some(script) => out.push_str(&script),
+                    None => out.push_str(&rest[..close]),
+                }
+                close
+            }
+            _ => {
+                out.push_str(&rest[..tag.end]);
+                tag.end
+            }
+        };
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    (out, embedded)
+}
+
+/// Start tag of an element, with its attributes.
+struct Tag {
+    /// Lowercase name of the element.
+    name: String,
+    /// Lowercase names of the attributes and their values, with the range of
+    /// each in the tag.
+    attributes: Vec<(String, String, Range<usize>)>,
+    /// End of the tag, after the `>`.
+    end: usize,
+}
+
+impl Tag {
+    /// Reads the start tag at the beginning of `src`, if there is one.
+    fn parse(src: &str) -> Option<Self> {
+        let bytes = src.as_bytes();
+        let name_end = src[1..]
+            .find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+            .map_or(src.len(), |end| end + 1);
+        let name = &src[1..name_end];
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+
+        let mut attributes = vec![];
+        let mut i = name_end;
+        loop {
+            while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+                i += 1;
+            }
+            match bytes.get(i) {
+                None => return None,
+                Some(b'>') => break,
+                Some(_) => {}
+            }
+            let start = i;
+            while i < bytes.len() && !b" \t\r\n\x0c/>=".contains(&bytes[i]) {
+                i += 1;
+            }
+            let attribute = src[start..i].to_ascii_lowercase();
+            let mut value = "";
+            if bytes.get(i) == Some(&b'=') {
+                i += 1;
+                match bytes.get(i) {
+                    Some(&quote) if quote == b'"' || quote == b'\'' => {
+                        let len = src[i + 1..].find(quote as char)?;
+                        value = &src[i + 1..i + 1 + len];
+                        i += len + 2;
+                    }
+                    _ => {
+                        let len = src[i..]
+                            .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                            .unwrap_or(src.len() - i);
+                        value = &src[i..i + len];
+                        i += len;
+                    }
+                }
+            }
+            attributes.push((attribute, value.replace("&amp;", "&"), start..i));
+        }
+        Some(Self {
+            name: name.to_ascii_lowercase(),
+            attributes,
+            end: i + 1,
+        })
+    }
+
+    fn attribute(&self, name: &str) -> Option<(&str, &Range<usize>)> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _, _)| attribute == name)
+            .map(|(_, value, range)| (value.as_str(), range))
+    }
+
+    /// The tag without the attribute at `range`.
+    fn without(&self, src: &str, range: &Range<usize>) -> String {
+        format!(
+            "{}{}",
+            src[..range.start].trim_end(),
+            &src[range.end..self.end]
+        )
+    }
+}
+
+/// Stylesheets become `<style>` elements, icons and other files get `data:`
+/// URIs.
+fn link(
+    tag: &Tag,
+    src: &str,
+    templates: &Path,
+    stylesheets: &[(&str, String)],
+    embedded: &mut Vec<PathBuf>,
+) -> String {
+    let unchanged = src[..tag.end].to_string();
+    let Some((href, range)) = tag.attribute("href") else {
+        return unchanged;
+    };
+    let Some(path) = local(href, templates) else {
+        return unchanged;
+    };
+    let stylesheet = tag.attribute("rel").is_some_and(|(rel, _)| {
+        rel.split_ascii_whitespace()
+            .any(|rel| rel.eq_ignore_ascii_case("stylesheet"))
+    });
+    if !stylesheet {
+        let Some(uri) = data_uri(&path) else {
+            return unchanged;
+        };
+        embedded.push(path);
+        return format!(
+            "{}href=\"{}\"{}",
+            &src[..range.start],
+            uri,
+            &src[range.end..tag.end]
+        );
+    }
+
+    let css = match stylesheets.iter().find(|(url, _)| *url == href) {
+        Some((_, css)) => css.clone(),
+        None => match std::fs::read_to_string(&path) {
+            Ok(css) => {
+                let css = css_urls(&css, path.parent().unwrap_or(Path::new("")), embedded);
+                embedded.push(path);
+                css
+            }
+            Err(err) => {
+                diagnostics::error(format_args!(
+                    "could not inline `{}`: {}",
+                    path.display(),
+                    err
+                ));
+                return unchanged;
+            }
+        },
+    };
+    match tag.attribute("media") {
+        Some((media, _)) => format!("<style media=\"{}\">\n{}</style>", media, css),
+        None => format!("<style>\n{}</style>", css),
+    }
+}
+
+/// Scripts loaded from a file get its contents instead.
+fn script(tag: &Tag, src: &str, templates: &Path, embedded: &mut Vec<PathBuf>) -> Option<String> {
+    let (url, range) = tag.attribute("src")?;
+    let path = local(url, templates)?;
+    let js = match std::fs::read_to_string(&path) {
+        Ok(js) => js,
+        Err(err) => {
+            diagnostics::error(format_args!(
+                "could not inline `{}`: {}",
+                path.display(),
+                err
+            ));
+            return None;
+        }
+    };
+    embedded.push(path);
+    // Would end the element early
+    let js = js.replace("</script", "<\\/script");
+    Some(format!("{}{}", tag.without(src, range), js))
+}
+
+/// Turns the files stylesheets refer to with `url(...)`, relative to `dir`,
+/// into `data:` URIs.
+fn css_urls(css: &str, dir: &Path, embedded: &mut Vec<PathBuf>) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = find_ignore_case(rest, "url(") {
+        let (before, after) = rest.split_at(start + "url(".len());
+        out.push_str(before);
+        let Some(end) = after.find(')') else {
+            rest = after;
+            break;
+        };
+        let url = after[..end].trim().trim_matches(|c| c == '"' || c == '\'');
+        match local(url, dir).and_then(|path| Some((data_uri(&path)?, path))) {
+            Some((uri, path)) => {
+                out.push_str(&format!("\"{}\"", uri));
+                embedded.push(path);
+            }
+            None => out.push_str(&after[..end]),
+        }
+        rest = &after[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Path of a URL relative to `dir`. Other URLs are left to the browser,
+/// with a warning for those loaded from elsewhere.
+fn local(url: &str, dir: &Path) -> Option<PathBuf> {
+    if url.is_empty() || url.starts_with('#') || url.starts_with("data:") {
+        return None;
+    }
+    if links::has_scheme(url) {
+        diagnostics::warning(format_args!(
+            "the page loads `{}` from elsewhere, so it is not standalone",
+            url
+        ));
+        return None;
+    }
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    Some(dir.join(path.trim_start_matches('/')))
+}
+
+/// The contents of `path` as a `data:` URI, reporting files that can not be
+/// read.
+fn data_uri(path: &Path) -> Option<String> {
+    match std::fs::read(path) {
+        Ok(contents) => Some(format!(
+            "data:{};base64,{}",
+            assets::content_type(path).replace(' ', ""),
+            STANDARD.encode(contents)
+        )),
+        Err(err) => {
+            diagnostics::error(format_args!(
+                "could not embed `{}`: {}",
+                path.display(),
+                err
+            ));
+            None
+        }
+    }
+}
+
+/// Byte offset of the first `needle`, which has to be lowercase, in any case.
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
@@ -0,0 +1,438 @@
+//! Index over all input notes, used to resolve links between them.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{self as md, Event, Tag, TagEnd};
+
+use crate::diagnostics::{self, Span};
+use crate::metadata::{self, FrontMatter};
+use crate::parser::{self, Parser};
+use crate::split::{Chunks, Preface, Split};
+
+/// A markdown file passed as input.
+#[derive(Debug)]
+pub struct Note {
+    pub path: PathBuf,
+    /// Absolute path without links, to find the note by other spellings of
+    /// its path.
+    pub canonical: Option<PathBuf>,
+    /// File stem, which is what `[[wiki links]]` refer to.
+    pub name: String,
+    pub src: String,
+    pub meta: FrontMatter,
+    /// Where the note is split into blocks.
+    pub split: Split,
+    /// What is done with the content before the first split.
+    pub preface: Preface,
+    /// Options the note is parsed with, the vault's with the extensions of
+    /// its front matter.
+    pub options: md::Options,
+    pub parser: Parser,
+    /// Blocks indexed by chunk id, chunk 0 being the content before the first
+    /// split.
+    pub blocks: Vec<BlockIndex>,
+}
+
+/// What we know about a block before rendering it.
+#[derive(Debug, Default, Clone, Hash)]
+pub struct BlockIndex {
+    /// Anchor id of the rendered block.
+    pub id: String,
+    /// Text of the heading the block starts with.
+    pub title: Option<String>,
+    /// Text of all headings in the block, including the title.
+    pub headings: Vec<String>,
+    /// Classes from the attributes of the title, `# Title {.wide}`.
+    pub classes: Vec<String>,
+    /// Other attributes of the title, `# Title {span=2}`.
+    pub attrs: Vec<(String, String)>,
+    /// Whether the block contains any events, empty blocks are not rendered.
+    pub empty: bool,
+}
+
+impl Note {
+    /// Parses the note into events with their source ranges.
+    pub fn events(&self) -> Vec<(Event<'_>, Range<usize>)> {
+        parser::parse(&self.src, self.parser, self.options)
+    }
+
+    /// Anchor of the first rendered block of this note.
+    pub fn anchor(&self) -> Option<&BlockIndex> {
+        self.blocks.iter().find(|block| !block.empty)
+    }
+}
+
+#[derive(Debug)]
+pub struct Vault {
+    pub root: PathBuf,
+    /// Folder embedded files are looked up in, relative to the vault root or,
+    /// if it starts with `./`, to the embedding note.
+    pub attachments: Option<PathBuf>,
+    /// Options the notes are parsed with, unless their front matter changes
+    /// them.
+    pub options: md::Options,
+    pub notes: Vec<Note>,
+}
+
+impl Vault {
+    pub fn new(
+        inputs: &[PathBuf],
+        root: Option<PathBuf>,
+        attachments: Option<PathBuf>,
+        options: md::Options,
+        parser: Parser,
+        split: Split,
+        preface: Preface,
+    ) -> Self {
+        let root = root.unwrap_or_else(|| common_root(inputs));
+
+        let mut ids = HashSet::new();
+        let mut notes = inputs
+            .iter()
+            .filter_map(|path| {
+                let src = std::fs::read_to_string(path)
+                    .map_err(|err| {
+                        diagnostics::error(format_args!(
+                            "could not read {}: {}",
+                            path.display(),
+                            err
+                        ))
+                    })
+                    .ok()?;
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                let meta = metadata::parse(&src, options).unwrap_or_else(|err| {
+                    diagnostics::warning_at(
+                        &Span::new(path, &src, err.range),
+                        format_args!("invalid front matter: {}", err.message),
+                    );
+                    FrontMatter::default()
+                });
+                let split = meta.split.unwrap_or(split);
+                let preface = meta.preface.unwrap_or(preface);
+                // The front matter was read, so it stays a metadata block
+                let metadata = md::Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+                    | md::Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
+                let options = meta.extensions.as_ref().map_or(options, |extensions| {
+                    extensions.options(options) | (options & metadata)
+                });
+                let parser = meta.parser.unwrap_or(parser);
+                let parser = match parser::check(&src, parser, options) {
+                    Ok(()) => parser,
+                    Err(err) => {
+                        diagnostics::warning_at(
+                            &Span::new(path, &src, err.range),
+                            format_args!(
+                                "could not parse note, reading it with pulldown-cmark instead: {}",
+                                err.message
+                            ),
+                        );
+                        Parser::PulldownCmark
+                    }
+                };
+                let (mut blocks, dropped) =
+                    index_blocks(&name, &src, options, parser, split, preface);
+                // Blocks with a title claim their ids first
+                let (titled, untitled): (Vec<_>, Vec<_>) =
+                    blocks.iter_mut().partition(|block| block.title.is_some());
+                for block in titled.into_iter().chain(untitled) {
+                    block.id = unique_id(std::mem::take(&mut block.id), &mut ids);
+                }
+                if dropped {
+                    diagnostics::warning(format_args!(
+                        "dropped content before the first block of {}",
+                        path.display()
+                    ));
+                }
+                Some(Note {
+                    path: path.clone(),
+                    canonical: path.canonicalize().ok(),
+                    name,
+                    src,
+                    meta,
+                    split,
+                    preface,
+                    options,
+                    parser,
+                    blocks,
+                })
+            })
+            .collect::<Vec<_>>();
+        notes.sort_by_key(|note| (note.meta.order.is_none(), note.meta.order));
+
+        Self {
+            root,
+            attachments,
+            options,
+            notes,
+        }
+    }
+
+    /// Looks up a file embedded in the note `from` the way Obsidian does: in
+    /// the folder of the note, the vault root and the attachments folder.
+    pub fn find_asset(&self, from: usize, name: &str) -> Option<PathBuf> {
+        let dir = self.notes[from].path.parent().unwrap_or(Path::new(""));
+        let mut candidates = vec![dir.join(name), self.root.join(name)];
+        if let Some(attachments) = &self.attachments {
+            if attachments.starts_with(".") {
+                candidates.push(dir.join(attachments).join(name));
+            } else {
+                candidates.push(self.root.join(attachments).join(name));
+            }
+        }
+        let found = candidates.iter().find(|path| path.is_file()).cloned();
+        if found.is_none() {
+            candidates.into_iter().for_each(missed);
+        }
+        found
+    }
+
+    /// Looks up a note the way Obsidian does, by file name or by a path
+    /// relative to the vault root, ignoring case and the `.md` extension.
+    /// The title from the front matter works as well.
+    pub fn find_note(&self, target: &str) -> Option<usize> {
+        let target = target.trim().trim_end_matches(".md").to_lowercase();
+        self.notes.iter().position(|note| {
+            if note.name.to_lowercase() == target
+                || note
+                    .meta
+                    .title
+                    .as_ref()
+                    .is_some_and(|title| title.to_lowercase() == target)
+            {
+                return true;
+            }
+            let path = note.path.with_extension("");
+            let relative = pathdiff::diff_paths(&path, &self.root).unwrap_or(path);
+            relative.to_string_lossy().replace('\\', "/").to_lowercase() == target
+        })
+    }
+
+    /// Resolves a `[[target#heading]]` link relative to the note `from`.
+    ///
+    /// An empty target refers to `from` itself. If no note matches the target,
+    /// it is looked up as the title of a block instead.
+    pub fn resolve(&self, from: usize, target: &str, heading: Option<&str>) -> Option<&BlockIndex> {
+        let note = if target.trim().is_empty() {
+            Some(from)
+        } else {
+            self.find_note(target)
+        };
+
+        let Some(note) = note else {
+            if heading.is_some() {
+                return None;
+            }
+            let target = target.trim().to_lowercase();
+            return self
+                .notes
+                .iter()
+                .flat_map(|note| note.blocks.iter())
+                .find(|block| {
+                    !block.empty
+                        && block
+                            .title
+                            .as_ref()
+                            .is_some_and(|title| title.to_lowercase() == target)
+                });
+        };
+
+        self.resolve_in(note, heading)
+    }
+
+    /// Resolves a heading of `note`, given as its text or its slug, to the
+    /// block containing it, or the first block if there is no heading.
+    pub fn resolve_in(&self, note: usize, heading: Option<&str>) -> Option<&BlockIndex> {
+        let note = &self.notes[note];
+        match heading {
+            None => note.anchor(),
+            Some(heading) => {
+                let heading = heading.trim().to_lowercase();
+                note.blocks.iter().find(|block| {
+                    block.headings.iter().any(|other| {
+                        other.to_lowercase() == heading || slug::slugify(other) == heading
+                    })
+                })
+            }
+        }
+    }
+
+    /// Finds the note at `path`, which may be spelled differently than the
+    /// input it was read from.
+    pub fn find_note_by_path(&self, path: &Path) -> Option<usize> {
+        let path = path.canonicalize().ok()?;
+        self.notes
+            .iter()
+            .position(|note| note.canonical.as_ref() == Some(&path))
+    }
+
+    /// Whether any block has the anchor `id`.
+    pub fn has_block(&self, id: &str) -> bool {
+        self.notes
+            .iter()
+            .flat_map(|note| note.blocks.iter())
+            .any(|block| block.id == id)
+    }
+}
+
+thread_local! {
+    /// Files looked up since `capture_missing` was called, if it was, that
+    /// did not exist.
+    static MISSING: RefCell<Option<Vec<PathBuf>>> = const { RefCell::new(None) };
+}
+
+/// Records that a file links refer to does not exist, so whatever links to
+/// it is rendered again once it does.
+pub fn missed(path: PathBuf) {
+    MISSING.with_borrow_mut(|missing| {
+        if let Some(missing) = missing {
+            missing.push(path);
+        }
+    });
+}
+
+/// Runs `f` and returns the files it looked up that did not exist.
+pub fn capture_missing<T>(f: impl FnOnce() -> T) -> (T, Vec<PathBuf>) {
+    let outer = MISSING.replace(Some(vec![]));
+    let result = f();
+    let missing = MISSING.replace(outer).unwrap_or_default();
+    (result, missing)
+}
+
+/// Whether `key` can be used in the name of a `data-` attribute.
+fn is_attribute_name(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Splits a note into blocks the same way `main` does, and records the
+/// headings of every block. Also returns whether content before the first
+/// split was dropped.
+///
+/// Ids are not unique yet, as that depends on the other notes.
+#[comemo::memoize]
+fn index_blocks(
+    name: &str,
+    src: &str,
+    options: md::Options,
+    parser: Parser,
+    split: Split,
+    preface: Preface,
+) -> (Vec<BlockIndex>, bool) {
+    let mut blocks: Vec<BlockIndex> = vec![];
+    // Text of the heading being read, and whether it is the title of its block
+    let mut heading: Option<(String, bool)> = None;
+    // Id from the attributes of the title, `# Title {#id}`
+    let mut explicit_id = None;
+    let mut in_metadata_block = false;
+
+    let events = parser::parse(src, parser, options);
+    let mut chunks = Chunks::new(events.into_iter(), split, preface);
+    for (chunk, event, _) in chunks.by_ref() {
+        while blocks.len() <= chunk {
+            blocks.push(BlockIndex {
+                empty: true,
+                ..Default::default()
+            });
+        }
+        match event {
+            Event::Start(Tag::MetadataBlock(_)) => in_metadata_block = true,
+            Event::End(TagEnd::MetadataBlock(_)) => {
+                in_metadata_block = false;
+                continue;
+            }
+            _ => {}
+        }
+        // The front matter is not rendered, so it does not count as content
+        if in_metadata_block {
+            continue;
+        }
+        let block = &mut blocks[chunk];
+        let first = block.empty;
+        block.empty = false;
+
+        match event {
+            // A heading the block starts with is its title
+            Event::Start(Tag::Heading {
+                id, classes, attrs, ..
+            }) => {
+                if first {
+                    explicit_id = id.map(|id| id.to_string());
+                    block.classes = classes.iter().map(|class| class.to_string()).collect();
+                    // Keys become attribute names, which can not be escaped
+                    block.attrs = attrs
+                        .iter()
+                        .filter(|(key, _)| is_attribute_name(key))
+                        .map(|(key, value)| {
+                            let value = value.as_ref().map_or("", |value| value.as_ref());
+                            (key.to_string(), value.to_string())
+                        })
+                        .collect();
+                }
+                heading = Some((String::new(), first));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((heading, _)) = &mut heading {
+                    heading.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                let (text, title) = heading.take().unwrap();
+                if title {
+                    let id = explicit_id.take().unwrap_or_else(|| {
+                        format!("{}-{}", slug::slugify(name), slug::slugify(&text))
+                    });
+                    block.id = id;
+                    block.title = Some(text.clone());
+                }
+                block.headings.push(text);
+            }
+            _ => {}
+        }
+    }
+
+    // Blocks without a title are named after the note and their position
+    for (chunk, block) in blocks.iter_mut().enumerate() {
+        if block.id.is_empty() {
+            let id = match chunk {
+                0 => slug::slugify(name),
+                chunk => format!("{}-{}", slug::slugify(name), chunk),
+            };
+            block.id = id;
+        }
+    }
+
+    (blocks, chunks.dropped_preface())
+}
+
+fn unique_id(id: String, ids: &mut HashSet<String>) -> String {
+    let mut unique = id.clone();
+    let mut i = 1;
+    while !ids.insert(unique.clone()) {
+        i += 1;
+        unique = format!("{}-{}", id, i);
+    }
+    unique
+}
+
+/// Deepest directory containing all inputs.
+fn common_root(inputs: &[PathBuf]) -> PathBuf {
+    let mut parents = inputs
+        .iter()
+        .map(|path| path.parent().unwrap_or(Path::new("")).to_path_buf());
+    let Some(mut root) = parents.next() else {
+        return PathBuf::new();
+    };
+    for parent in parents {
+        while !parent.starts_with(&root) {
+            if !root.pop() {
+                break;
+            }
+        }
+    }
+    root
+}
@@ -0,0 +1,147 @@
+//! Building again whenever the files a build used change.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use notify_debouncer_full::new_debouncer;
+use notify_debouncer_full::notify::RecursiveMode;
+
+use crate::cache::Cache;
+use crate::config::Config;
+use crate::error::Error;
+use crate::input::is_pattern;
+use crate::{build, diagnostics, Mode};
+
+/// Changes are collected until there were none for this long, so saving
+/// several files at once only builds once.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Number of builds memoized results are kept for without being used.
+const MEMOIZE_BUILDS: usize = 10;
+
+/// Builds, then builds again whenever a note, an asset or a template changes,
+/// calling `on_build` after every build that worked. Errors are printed and
+/// the files are watched for the fix, only errors watching them end the loop.
+pub fn watch(config: &Config, mut on_build: impl FnMut()) -> Result<(), Error> {
+    let (sender, receiver) = mpsc::channel();
+    let mut debouncer = new_debouncer(DEBOUNCE, None, sender)?;
+    let mut cache = Cache::load(&config.out);
+    let mut watched: HashMap<PathBuf, RecursiveMode> = HashMap::new();
+    let mut sources = vec![];
+
+    loop {
+        let start = Instant::now();
+        match build(config, &mut cache, Mode::Write) {
+            Ok(report) => {
+                sources = report.sources.iter().map(|path| absolute(path)).collect();
+                report.print();
+                println!("built in {:.2?}", start.elapsed());
+                on_build();
+            }
+            // Keep watching the files of the last build that worked
+            Err(err) => diagnostics::report(&err),
+        }
+        comemo::evict(MEMOIZE_BUILDS);
+
+        let paths = watch_paths(config, &sources);
+        for path in watched.keys() {
+            if !paths.contains_key(path) {
+                debouncer.unwatch(path).ok();
+            }
+        }
+        for (path, mode) in &paths {
+            if watched.get(path) != Some(mode) {
+                if let Err(err) = debouncer.watch(path, *mode) {
+                    diagnostics::warning(format_args!(
+                        "could not watch {}: {}",
+                        path.display(),
+                        err
+                    ));
+                }
+            }
+        }
+        watched = paths;
+        println!("watching for changes");
+
+        let out = absolute(&config.out);
+        loop {
+            let events = receiver
+                .recv()
+                .expect("the debouncer stopped")
+                .map_err(|mut errors| errors.remove(0))?;
+            // Reading the files while building is reported as well
+            let changed = events
+                .iter()
+                .filter(|event| !event.kind.is_access())
+                .flat_map(|event| &event.paths)
+                .any(|changed| {
+                    !changed.starts_with(&out)
+                        && !is_hidden(changed)
+                        && (sources.contains(changed)
+                            || watched.iter().any(|(path, mode)| {
+                                *mode == RecursiveMode::Recursive && changed.starts_with(path)
+                            }))
+                });
+            if changed {
+                break;
+            }
+        }
+    }
+}
+
+/// Folders to watch for changes: the templates, the folders and glob patterns
+/// notes are searched in, and the folders of the files a build used.
+///
+/// Folders are watched instead of files, because editors often save files by
+/// replacing them, which ends watching the replaced file.
+fn watch_paths(config: &Config, sources: &[PathBuf]) -> HashMap<PathBuf, RecursiveMode> {
+    let mut recursive = vec![absolute(&config.templates)];
+    let mut parents = vec![];
+    for input in &config.inputs {
+        if input.is_dir() {
+            recursive.push(absolute(input));
+        } else if input.is_file() || !is_pattern(input) {
+            parents.push(parent(&absolute(input)));
+        } else {
+            recursive.push(pattern_root(&absolute(input)));
+        }
+    }
+    parents.extend(sources.iter().map(|path| parent(path)));
+
+    let mut paths = HashMap::new();
+    for path in recursive {
+        paths.insert(path, RecursiveMode::Recursive);
+    }
+    for path in parents {
+        // Folders inside a watched folder are watched already
+        if !paths.keys().any(|watched| path.starts_with(watched)) {
+            paths.insert(path, RecursiveMode::NonRecursive);
+        }
+    }
+    paths
+}
+
+/// Path starting at `/`, which is how the changes are reported.
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn parent(path: &Path) -> PathBuf {
+    path.parent().unwrap_or(path).to_path_buf()
+}
+
+/// The folders of a glob pattern before the first one with a wildcard.
+fn pattern_root(pattern: &Path) -> PathBuf {
+    pattern
+        .components()
+        .take_while(|component| !is_pattern(Path::new(component)))
+        .collect()
+}
+
+/// Whether the file is hidden, like the swap files of editors.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
@@ -0,0 +1,614 @@
+//! Obsidian style `[[wiki links]]`.
+
+use std::collections::VecDeque;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::utils::TextMergeStream;
+use pulldown_cmark::{CowStr, Event, LinkType, Tag, TagEnd};
+
+use crate::diagnostics::{self, Span};
+use crate::vault::{Note, Vault};
+
+/// How deep transclusions may be nested before giving up.
+const MAX_TRANSCLUSION_DEPTH: usize = 8;
+
+/// Parsed contents of `[[target#heading|alias]]` or `![[target#heading|alias]]`.
+#[derive(Debug)]
+pub struct WikiLink<'s> {
+    pub embed: bool,
+    pub target: &'s str,
+    pub heading: Option<&'s str>,
+    pub alias: Option<&'s str>,
+    /// Size of embeds, `![[image.png|100]]` or `![[image.png|100x50]]`.
+    pub size: Option<(u32, Option<u32>)>,
+}
+
+impl<'s> WikiLink<'s> {
+    fn parse(inner: &'s str, embed: bool) -> Self {
+        let (target, mut alias) = match inner.split_once('|') {
+            Some((target, alias)) => (target, Some(alias.trim())),
+            None => (inner, None),
+        };
+        let mut size = None;
+        if let Some(rest) = alias.filter(|_| embed) {
+            let (rest, last) = match rest.rsplit_once('|') {
+                Some((rest, last)) => (Some(rest.trim()), last.trim()),
+                None => (None, rest),
+            };
+            size = parse_size(last);
+            if size.is_some() {
+                alias = rest;
+            }
+        }
+        let (target, heading) = match target.split_once('#') {
+            Some((target, heading)) => (target.trim(), Some(heading.trim())),
+            None => (target.trim(), None),
+        };
+        Self {
+            embed,
+            target,
+            heading,
+            alias,
+            size,
+        }
+    }
+
+    /// Text shown for the link, Obsidian displays `[[a#b]]` as "a > b".
+    pub fn label(&self) -> String {
+        if let Some(alias) = self.alias {
+            return alias.to_string();
+        }
+        match (self.target, self.heading) {
+            (target, None) => target.to_string(),
+            ("", Some(heading)) => heading.to_string(),
+            (target, Some(heading)) => format!("{} > {}", target, heading),
+        }
+    }
+
+    /// Finds the first wiki link in `text`, returning its byte range including
+    /// the brackets and the `!` of embeds.
+    pub fn find(text: &'s str) -> Option<(Range<usize>, Self)> {
+        let mut offset = 0;
+        while let Some(start) = text[offset..].find("[[") {
+            let start = offset + start;
+            let len = text[start + 2..].find("]]")?;
+            let inner = &text[start + 2..start + 2 + len];
+            let end = start + 2 + len + 2;
+            if inner.trim().is_empty() || inner.contains('[') {
+                offset = start + 1;
+                continue;
+            }
+            let embed = text[..start].ends_with('!');
+            let start = if embed { start - 1 } else { start };
+            return Some((start..end, Self::parse(inner, embed)));
+        }
+        None
+    }
+}
+
+fn parse_size(size: &str) -> Option<(u32, Option<u32>)> {
+    match size.split_once('x') {
+        Some((width, height)) => Some((width.parse().ok()?, Some(height.parse().ok()?))),
+        None => Some((size.parse().ok()?, None)),
+    }
+}
+
+/// Whether a file can be embedded as an `<img>`.
+fn is_image(path: &Path) -> bool {
+    const EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "webp", "bmp", "avif"];
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Makes a relative path `url` relative to `to` instead of `from`, leaving
+/// anchors and URLs with a scheme untouched.
+fn rebase<'a>(url: CowStr<'a>, from: &Path, to: &Path) -> CowStr<'a> {
+    if from == to || url.starts_with('#') || url.starts_with('/') || url.contains(':') {
+        return url;
+    }
+    match pathdiff::diff_paths(from.join(url.as_ref()), to) {
+        Some(path) => path.to_string_lossy().replace('\\', "/").into(),
+        None => url,
+    }
+}
+
+/// Notes whose content the events transclude, directly or through other
+/// notes, so the rendered block has to be updated when they change.
+pub fn transcluded(vault: &Vault, note: usize, events: &[Event]) -> Vec<usize> {
+    let mut notes = vec![];
+    let mut queue = vec![(note, events.to_vec())];
+    while let Some((from, events)) = queue.pop() {
+        for event in TextMergeStream::new(events.into_iter()) {
+            let Event::Text(text) = event else {
+                continue;
+            };
+            let mut rest = text.as_ref();
+            while let Some((range, link)) = WikiLink::find(rest) {
+                rest = &rest[range.end..];
+                if !link.embed || is_image(Path::new(link.target)) {
+                    continue;
+                }
+                let target = if link.target.is_empty() {
+                    Some(from)
+                } else {
+                    vault.find_note(link.target)
+                };
+                if let Some(target) = target.filter(|target| !notes.contains(target)) {
+                    notes.push(target);
+                    let note = &vault.notes[target];
+                    queue.push((
+                        target,
+                        note.events().into_iter().map(|(event, _)| event).collect(),
+                    ));
+                }
+            }
+        }
+    }
+    notes
+}
+
+/// Returns the events of a note with their source ranges, or of the section
+/// under `heading` including the heading itself. The metadata block of the
+/// note is skipped.
+fn section<'a>(note: &'a Note, heading: Option<&str>) -> Option<Vec<(Event<'a>, Range<usize>)>> {
+    let mut in_metadata_block = false;
+    let events = note
+        .events()
+        .into_iter()
+        .filter(|(event, _)| match event {
+            Event::Start(Tag::MetadataBlock(_)) => {
+                in_metadata_block = true;
+                false
+            }
+            Event::End(TagEnd::MetadataBlock(_)) => {
+                in_metadata_block = false;
+                false
+            }
+            _ => !in_metadata_block,
+        })
+        .collect::<Vec<_>>();
+    let Some(heading) = heading else {
+        return Some(events);
+    };
+    let heading = heading.to_lowercase();
+
+    let (start, level) = events
+        .iter()
+        .enumerate()
+        .find_map(|(i, (event, _))| match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                let text = events[i..]
+                    .iter()
+                    .take_while(|(event, _)| !matches!(event, Event::End(TagEnd::Heading(_))))
+                    .filter_map(|(event, _)| match event {
+                        Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                        _ => None,
+                    })
+                    .collect::<String>();
+                (text.trim().to_lowercase() == heading).then_some((i, *level))
+            }
+            _ => None,
+        })?;
+    let end = events[start + 1..]
+        .iter()
+        .position(|(event, _)| matches!(event, Event::Start(Tag::Heading { level: other, .. }) if *other <= level))
+        .map_or(events.len(), |len| start + 1 + len);
+
+    Some(events[start..end].to_vec())
+}
+
+/// Iterator adaptor replacing wiki links in text events with links to the
+/// blocks they resolve to, and embeds with the images or notes they refer to.
+///
+/// Events are paired with their source ranges, events replacing a wiki link
+/// get the range of the link.
+pub struct WikiLinks<'a, I: Iterator<Item = (Event<'a>, Range<usize>)>> {
+    iter: Peekable<I>,
+    vault: &'a Vault,
+    /// Index of the note the events belong to.
+    note: usize,
+    queue: VecDeque<(Event<'a>, Range<usize>)>,
+    /// Source range of the wiki link being expanded.
+    range: Range<usize>,
+    /// Whether inside a code or metadata block, where links are not expanded.
+    in_literal_block: bool,
+    /// Whether inside a paragraph, which has to be split around transclusions.
+    in_paragraph: bool,
+    /// Notes and sections currently being transcluded, starting with the note
+    /// the events belong to.
+    stack: Vec<(usize, Option<String>)>,
+}
+
+impl<'a, I> WikiLinks<'a, I>
+where
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    pub fn new(iter: I, vault: &'a Vault, note: usize) -> Self {
+        Self::nested(iter, vault, note, vec![(note, None)])
+    }
+
+    fn nested(iter: I, vault: &'a Vault, note: usize, stack: Vec<(usize, Option<String>)>) -> Self {
+        Self {
+            iter: iter.peekable(),
+            vault,
+            note,
+            queue: VecDeque::new(),
+            range: 0..0,
+            in_literal_block: false,
+            in_paragraph: false,
+            stack,
+        }
+    }
+
+    /// Next event, with consecutive text events merged the way
+    /// `TextMergeStream` does.
+    fn next_event(&mut self) -> Option<(Event<'a>, Range<usize>)> {
+        let (event, mut range) = self.iter.next()?;
+        let Event::Text(mut text) = event else {
+            return Some((event, range));
+        };
+        while let Some((Event::Text(next), next_range)) = self
+            .iter
+            .next_if(|(event, _)| matches!(event, Event::Text(_)))
+        {
+            text = format!("{}{}", text, next).into();
+            range.end = next_range.end;
+        }
+        Some((Event::Text(text), range))
+    }
+
+    /// Pushes an event replacing the wiki link being expanded.
+    fn push(&mut self, event: Event<'a>) {
+        self.queue.push_back((event, self.range.clone()));
+    }
+
+    /// Place of the wiki link being expanded, for messages about it.
+    fn span(&self) -> Span<'a> {
+        let note = &self.vault.notes[self.note];
+        Span::new(&note.path, &note.src, self.range.clone())
+    }
+
+    /// Replaces the wiki links in `text`, which was read from `range` of the
+    /// note.
+    fn expand(&mut self, mut text: &str, range: Range<usize>) {
+        // Escapes and entities make the text differ from the source, then
+        // only the range of the whole text is known
+        let exact = self.vault.notes[self.note].src.get(range.clone()) == Some(text);
+        let mut offset = exact.then_some(range.start);
+        let source = |offset: Option<usize>, local: Range<usize>| match offset {
+            Some(offset) => offset + local.start..offset + local.end,
+            None => range.clone(),
+        };
+        while let Some((link_range, link)) = WikiLink::find(text) {
+            if link_range.start > 0 {
+                self.range = source(offset, 0..link_range.start);
+                self.push(Event::Text(text[..link_range.start].to_string().into()));
+            }
+            self.range = source(offset, link_range.clone());
+            let path = Path::new(link.target);
+            if link.embed && is_image(path) {
+                self.push_image(&link);
+            } else if link.embed && path.extension().is_none_or(|extension| extension == "md") {
+                self.transclude(&link);
+            } else {
+                self.push_link(&link);
+            }
+            text = &text[link_range.end..];
+            offset = offset.map(|offset| offset + link_range.end);
+        }
+        if !text.is_empty() {
+            self.range = source(offset, 0..text.len());
+            self.push(Event::Text(text.to_string().into()));
+        }
+    }
+
+    /// Buffers a whole paragraph, so paragraphs left empty by transclusions
+    /// can be dropped.
+    fn paragraph(&mut self, range: Range<usize>) {
+        self.in_paragraph = true;
+        self.queue.push_back((Event::Start(Tag::Paragraph), range));
+        while let Some((event, range)) = self.next_event() {
+            match event {
+                Event::Text(text) if text.contains("[[") => self.expand(&text, range),
+                Event::End(TagEnd::Paragraph) => {
+                    self.queue.push_back((event, range));
+                    break;
+                }
+                event => self.queue.push_back((event, range)),
+            }
+        }
+        self.in_paragraph = false;
+
+        let mut events = std::mem::take(&mut self.queue).into_iter().peekable();
+        while let Some(event) = events.next() {
+            if matches!(event.0, Event::Start(Tag::Paragraph))
+                && matches!(events.peek(), Some((Event::End(TagEnd::Paragraph), _)))
+            {
+                events.next();
+                continue;
+            }
+            self.queue.push_back(event);
+        }
+    }
+
+    /// Pushes `text` wrapped in a span with the given class, used to mark
+    /// links and embeds that could not be resolved.
+    fn push_span(&mut self, class: &str, text: String) {
+        self.push(Event::InlineHtml(
+            format!(r#"<span class="{}">"#, class).into(),
+        ));
+        self.push(Event::Text(text.into()));
+        self.push(Event::InlineHtml("</span>".into()));
+    }
+
+    /// Pushes an image with a path relative to the note, so it is picked up
+    /// like any other image.
+    fn push_image(&mut self, link: &WikiLink) {
+        let Some(path) = self.vault.find_asset(self.note, link.target) else {
+            diagnostics::warning_at(
+                &self.span(),
+                format_args!("embedded file `{}` not found", link.target),
+            );
+            self.push_span("embed-missing", link.target.to_string());
+            return;
+        };
+
+        let dest_url = self.relative_url(path);
+
+        if let Some((width, height)) = link.size {
+            let style = match height {
+                Some(height) => format!("width: {}px; height: {}px", width, height),
+                None => format!("width: {}px", width),
+            };
+            self.push(Event::InlineHtml(
+                format!(r#"<span class="embed" style="{}">"#, style).into(),
+            ));
+        }
+        self.push(Event::Start(Tag::Image {
+            link_type: LinkType::Inline,
+            dest_url: dest_url.into(),
+            title: "".into(),
+            id: "".into(),
+        }));
+        self.push(Event::Text(
+            link.alias.unwrap_or(link.target).to_string().into(),
+        ));
+        self.push(Event::End(TagEnd::Image));
+        if link.size.is_some() {
+            self.push(Event::InlineHtml("</span>".into()));
+        }
+    }
+
+    /// Inlines the events of another note, or one of its sections, with its
+    /// own links resolved relative to that note.
+    fn transclude(&mut self, link: &WikiLink) {
+        let from = &self.vault.notes[self.note];
+        let target = if link.target.is_empty() {
+            Some(self.note)
+        } else {
+            self.vault.find_note(link.target)
+        };
+        let Some(target) = target else {
+            diagnostics::warning_at(
+                &self.span(),
+                format_args!("embedded note `{}` not found", link.target),
+            );
+            self.push_span("embed-missing", link.label());
+            return;
+        };
+
+        let key = (target, link.heading.map(|heading| heading.to_lowercase()));
+        if let Some(start) = self.stack.iter().position(|other| *other == key) {
+            let cycle = self.stack[start..]
+                .iter()
+                .chain([&key])
+                .map(|(note, heading)| match heading {
+                    Some(heading) => format!("{}#{}", self.vault.notes[*note].name, heading),
+                    None => self.vault.notes[*note].name.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            diagnostics::error_at(
+                &self.span(),
+                format_args!("cyclic transclusion `{}`", cycle),
+            );
+            self.push_span("embed-error", link.label());
+            return;
+        }
+        if self.stack.len() > MAX_TRANSCLUSION_DEPTH {
+            diagnostics::error_at(
+                &self.span(),
+                format_args!(
+                    "transclusion of `{}` exceeds the depth limit of {}",
+                    link.label(),
+                    MAX_TRANSCLUSION_DEPTH
+                ),
+            );
+            self.push_span("embed-error", link.label());
+            return;
+        }
+
+        let note = &self.vault.notes[target];
+        let Some(events) = section(note, link.heading) else {
+            diagnostics::warning_at(
+                &self.span(),
+                format_args!("embedded section `{}` not found", link.label()),
+            );
+            self.push_span("embed-missing", link.label());
+            return;
+        };
+
+        let mut stack = self.stack.clone();
+        stack.push(key);
+        let nested = WikiLinks::nested(events.into_iter(), self.vault, target, stack);
+
+        // Relative paths in the embedded note have to be relative to this one
+        let from_dir = from.path.parent().unwrap_or(Path::new(""));
+        let to_dir = note.path.parent().unwrap_or(Path::new(""));
+        // Messages about the embedded note point at it, but its events take
+        // the place of the embed
+        let range = self.range.clone();
+        let nested = nested.map(|(event, _)| match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Image {
+                link_type,
+                dest_url: rebase(dest_url, to_dir, from_dir),
+                title,
+                id,
+            }),
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Link {
+                link_type,
+                dest_url: rebase(dest_url, to_dir, from_dir),
+                title,
+                id,
+            }),
+            event => event,
+        });
+        let nested = nested
+            .map(|event| (event, range.clone()))
+            .collect::<Vec<_>>();
+
+        if self.in_paragraph {
+            self.push(Event::End(TagEnd::Paragraph));
+        }
+        self.push(Event::Html("<div class=\"transclusion\">\n".into()));
+        self.queue.extend(nested);
+        self.push(Event::Html("</div>\n".into()));
+        if self.in_paragraph {
+            self.push(Event::Start(Tag::Paragraph));
+        }
+    }
+
+    /// Path of `path` relative to the note, the way a markdown link in the
+    /// note would refer to it.
+    fn relative_url(&self, path: PathBuf) -> String {
+        let dir = self.vault.notes[self.note]
+            .path
+            .parent()
+            .unwrap_or(Path::new(""));
+        let path = pathdiff::diff_paths(&path, dir).unwrap_or(path);
+        path.to_string_lossy().replace('\\', "/")
+    }
+
+    fn push_link(&mut self, link: &WikiLink) {
+        let label = link.label();
+        let dest_url = match self.vault.resolve(self.note, link.target, link.heading) {
+            Some(block) => format!("#{}", block.id),
+            // Links to other files are copied like any other local link
+            None => match self.vault.find_asset(self.note, link.target) {
+                Some(path) => self.relative_url(path),
+                None => {
+                    diagnostics::warning_at(
+                        &self.span(),
+                        format_args!("unresolved wiki link `[[{}]]`", label),
+                    );
+                    self.push_span("wikilink-unresolved", label);
+                    return;
+                }
+            },
+        };
+        self.push(Event::Start(Tag::Link {
+            link_type: LinkType::Inline,
+            dest_url: dest_url.into(),
+            title: "".into(),
+            id: "".into(),
+        }));
+        self.push(Event::Text(label.into()));
+        self.push(Event::End(TagEnd::Link));
+    }
+}
+
+impl<'a, I> Iterator for WikiLinks<'a, I>
+where
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    type Item = (Event<'a>, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.queue.is_empty() {
+            let (event, range) = self.next_event()?;
+            match &event {
+                Event::Start(Tag::CodeBlock(_) | Tag::MetadataBlock(_)) => {
+                    self.in_literal_block = true
+                }
+                Event::End(TagEnd::CodeBlock | TagEnd::MetadataBlock(_)) => {
+                    self.in_literal_block = false
+                }
+                Event::Start(Tag::Paragraph) => {
+                    self.paragraph(range);
+                    continue;
+                }
+                Event::Text(text) if !self.in_literal_block && text.contains("[[") => {
+                    let text = text.clone();
+                    self.expand(&text, range);
+                    continue;
+                }
+                _ => {}
+            }
+            return Some((event, range));
+        }
+        self.queue.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link() {
+        let link = WikiLink::parse("a#b|c", false);
+        assert_eq!(link.target, "a");
+        assert_eq!(link.heading, Some("b"));
+        assert_eq!(link.alias, Some("c"));
+        assert_eq!(link.size, None);
+        assert_eq!(link.label(), "c");
+    }
+
+    #[test]
+    fn label_without_alias() {
+        assert_eq!(WikiLink::parse("a#b", false).label(), "a > b");
+        assert_eq!(WikiLink::parse("#b", false).label(), "b");
+        assert_eq!(WikiLink::parse(" a ", false).label(), "a");
+    }
+
+    #[test]
+    fn embed_size() {
+        let link = WikiLink::parse("x.png|100x50", true);
+        assert_eq!(link.target, "x.png");
+        assert_eq!(link.alias, None);
+        assert_eq!(link.size, Some((100, Some(50))));
+
+        let link = WikiLink::parse("x.png|alt|100", true);
+        assert_eq!(link.alias, Some("alt"));
+        assert_eq!(link.size, Some((100, None)));
+    }
+
+    #[test]
+    fn size_only_for_embeds() {
+        let link = WikiLink::parse("x.png|100x50", false);
+        assert_eq!(link.alias, Some("100x50"));
+        assert_eq!(link.size, None);
+    }
+
+    #[test]
+    fn find() {
+        let (range, link) = WikiLink::find("see ![[x.png|100x50]] here").unwrap();
+        assert_eq!(range, 4..21);
+        assert!(link.embed);
+        assert_eq!(link.size, Some((100, Some(50))));
+        assert!(WikiLink::find("[[ ]] and [[a").is_none());
+    }
+}